@@ -0,0 +1,47 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use jsonparse::Parser;
+
+fn flat_object() -> String {
+    let entries: Vec<String> = (0..10_000).map(|i| format!("\"key{}\": {}", i, i)).collect();
+    format!("{{{}}}", entries.join(","))
+}
+
+fn deep_nesting() -> String {
+    let mut doc = String::from("1");
+    for i in 0..200 {
+        doc = if i % 2 == 0 {
+            format!("[{}]", doc)
+        } else {
+            format!("{{\"inner\": {}}}", doc)
+        };
+    }
+    doc
+}
+
+fn large_array() -> String {
+    let elements: Vec<String> = (0..50_000).map(|i| (i * 7).to_string()).collect();
+    format!("[{}]", elements.join(", "))
+}
+
+fn string_heavy() -> String {
+    let elements: Vec<String> = (0..5_000)
+        .map(|i| format!("{{\"name\": \"user number {}\", \"bio\": \"lorem ipsum dolor sit amet \\\"quoted\\\"\"}}", i))
+        .collect();
+    format!("[{}]", elements.join(","))
+}
+
+fn bench_documents(c: &mut Criterion) {
+    let documents = [
+        ("flat object", flat_object()),
+        ("deep nesting", deep_nesting()),
+        ("large array", large_array()),
+        ("string heavy", string_heavy()),
+    ];
+
+    for (name, input) in documents.iter() {
+        c.bench_function(name, |b| b.iter(|| Parser::new(black_box(input)).try_parse()));
+    }
+}
+
+criterion_group!(benches, bench_documents);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use jsonparse::Parser;
+
+fn repeated(item: &str, n: usize) -> String {
+    format!("[{}]", vec![item; n].join(","))
+}
+
+fn small_ints(c: &mut Criterion) {
+    let input = repeated("0,1", 10_000);
+    c.bench_function("small ints", |b| b.iter(|| Parser::new(black_box(&input)).try_parse()));
+}
+
+fn literals(c: &mut Criterion) {
+    let input = repeated("true,false,null", 10_000);
+    c.bench_function("literals", |b| b.iter(|| Parser::new(black_box(&input)).try_parse()));
+}
+
+criterion_group!(benches, small_ints, literals);
+criterion_main!(benches);
@@ -25,12 +25,12 @@ fn main() {
     }
     "#;
 
-    // Version with Option<&Value> sugar, returns None if index not found:
+    // Version with Option<Value> sugar, returns None if index not found:
 
     let p = Parser::new(json_str);
     let val = p.parse();
 
-    println!("{:?}", val.as_ref().get_map("inner_obj").get_map("inner_array_of_objects").get_arr(1));
+    println!("{:?}", val.get_map("inner_obj").get_map("inner_array_of_objects").get_arr(1));
 
 
     // Version with Index, panics if index not found:
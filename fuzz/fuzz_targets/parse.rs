@@ -0,0 +1,18 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use jsonparse::{from_slice, ParserBuilder};
+
+// Arbitrary bytes must only ever produce Ok or Err, never a panic
+fuzz_target!(|data: &[u8]| {
+    let _ = from_slice(data);
+
+    if let Ok(input) = std::str::from_utf8(data) {
+        let _ = ParserBuilder::new()
+            .allow_comments(true)
+            .allow_trailing_commas(true)
+            .big_integers(true)
+            .build(input)
+            .parse_with_comments();
+    }
+});
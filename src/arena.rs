@@ -0,0 +1,206 @@
+use bumpalo::Bump;
+use bumpalo::collections::Vec as BumpVec;
+use std::collections::HashMap;
+
+use crate::{Build, DuplicateKeys, ParseError, Parser, ParserOptions, Value};
+
+// Borrowed counterpart of Value, every node and string lives in the arena
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArenaValue<'b> {
+    Int(i64),
+    Float(f64),
+    BigInt(i128),
+    // A number with ParserOptions::raw_numbers, together with its literal
+    Number { value: f64, raw: &'b str },
+    JsonString(&'b str),
+    Array(&'b [ArenaValue<'b>]),
+    Object(&'b [(&'b str, ArenaValue<'b>)]),
+    Bool(bool),
+    Null,
+}
+
+impl<'b> ArenaValue<'b> {
+    pub fn get_arr(&self, i: usize) -> Option<&'b ArenaValue<'b>> {
+        match self {
+            ArenaValue::Array(v) => v.get(i),
+            _ => None,
+        }
+    }
+
+    // Objects are stored as entry slices, so lookup is linear. Under the default
+    // DuplicateKeys::TakeLast a repeated key keeps all of its entries and the last one wins,
+    // matching the HashMap behaviour of the owned Parser
+    pub fn get_map(&self, key: &str) -> Option<&'b ArenaValue<'b>> {
+        match self {
+            ArenaValue::Object(entries) => entries.iter()
+                .rev()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match *self {
+            ArenaValue::Int(i) => Value::Int(i),
+            ArenaValue::Float(f) => Value::Float(f),
+            ArenaValue::BigInt(b) => Value::BigInt(b),
+            ArenaValue::Number { value, raw } => Value::Number { value, raw: raw.to_string() },
+            ArenaValue::JsonString(s) => Value::JsonString(s.to_string()),
+            ArenaValue::Array(v) => Value::Array(v.iter().map(|val| val.to_value()).collect()),
+            ArenaValue::Object(entries) => {
+                let map: HashMap<String, Value> = entries.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_value()))
+                    .collect();
+                Value::Object(map)
+            },
+            ArenaValue::Bool(b) => Value::Bool(b),
+            ArenaValue::Null => Value::Null,
+        }
+    }
+}
+
+// Parses into a bump allocator, the whole document is freed at once when the Bump is
+// dropped. Strings are unescaped straight into the arena
+pub struct ArenaParser<'a, 'b> {
+    parser: Parser<'a>,
+    bump: &'b Bump,
+}
+
+impl<'a, 'b> ArenaParser<'a, 'b> {
+    pub fn new(input: &'a str, bump: &'b Bump) -> ArenaParser<'a, 'b> {
+        ArenaParser::with_options(input, ParserOptions::default(), bump)
+    }
+
+    // See also ParserBuilder::build_arena
+    pub fn with_options(input: &'a str, options: ParserOptions, bump: &'b Bump) -> ArenaParser<'a, 'b> {
+        ArenaParser {
+            parser: Parser::with_options(input, options),
+            bump,
+        }
+    }
+
+    // Like Parser::try_parse, with the same options and errors
+    pub fn try_parse(mut self) -> Result<&'b ArenaValue<'b>, ParseError> {
+        let mut arena = Arena {
+            bump: self.bump,
+            duplicate_keys: self.parser.options.duplicate_keys,
+        };
+        match self.parser.build_root(&mut arena) {
+            Ok(val) => Ok(self.bump.alloc(val)),
+            Err(e) => Err(self.parser.locate(e)),
+        }
+    }
+}
+
+struct Arena<'b> {
+    bump: &'b Bump,
+    duplicate_keys: DuplicateKeys,
+}
+
+struct ArenaObject<'b> {
+    entries: BumpVec<'b, (&'b str, ArenaValue<'b>)>,
+    // Where in entries each key is. Not needed for DuplicateKeys::TakeLast, which keeps
+    // every entry and leaves it to get_map to find the last one
+    index: HashMap<&'b str, usize>,
+    // All values of a key repeated under DuplicateKeys::Collect, by where it is in entries
+    collected: HashMap<usize, BumpVec<'b, ArenaValue<'b>>>,
+}
+
+impl<'b> Arena<'b> {
+    // Values a number_hook returns aren't limited to scalars
+    fn alloc(&self, val: &Value) -> ArenaValue<'b> {
+        match val.resolve() {
+            Value::Int(i) => ArenaValue::Int(*i),
+            Value::Float(f) => ArenaValue::Float(*f),
+            Value::BigInt(b) => ArenaValue::BigInt(*b),
+            Value::Number { value, raw } => ArenaValue::Number { value: *value, raw: self.bump.alloc_str(raw) },
+            Value::JsonString(s) => ArenaValue::JsonString(self.bump.alloc_str(s)),
+            Value::Array(v) => {
+                ArenaValue::Array(self.bump.alloc_slice_fill_iter(v.iter().map(|val| self.alloc(val))))
+            },
+            Value::Object(map) => {
+                let entries = map.iter().map(|(key, val)| (&*self.bump.alloc_str(key), self.alloc(val)));
+                ArenaValue::Object(self.bump.alloc_slice_fill_iter(entries))
+            },
+            Value::Bool(b) => ArenaValue::Bool(*b),
+            Value::Null => ArenaValue::Null,
+            Value::Shared(_) => unreachable!("resolve looks through Shared"),
+        }
+    }
+}
+
+impl<'b> Build for Arena<'b> {
+    type Value = ArenaValue<'b>;
+    type Array = BumpVec<'b, ArenaValue<'b>>;
+    type Object = ArenaObject<'b>;
+
+    fn scalar(&mut self, val: Value) -> ArenaValue<'b> {
+        self.alloc(&val)
+    }
+
+    fn string(&mut self, s: &mut String) -> ArenaValue<'b> {
+        ArenaValue::JsonString(self.bump.alloc_str(s))
+    }
+
+    fn array(&mut self) -> Self::Array {
+        BumpVec::new_in(self.bump)
+    }
+
+    fn push(&mut self, array: &mut Self::Array, val: ArenaValue<'b>) {
+        array.push(val);
+    }
+
+    fn end_array(&mut self, array: Self::Array) -> ArenaValue<'b> {
+        ArenaValue::Array(array.into_bump_slice())
+    }
+
+    fn object(&mut self) -> ArenaObject<'b> {
+        ArenaObject {
+            entries: BumpVec::new_in(self.bump),
+            index: HashMap::new(),
+            collected: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, object: &mut ArenaObject<'b>, key: &mut String, val: ArenaValue<'b>) -> Result<(), ParseError> {
+        if self.duplicate_keys == DuplicateKeys::TakeLast {
+            object.entries.push((self.bump.alloc_str(key), val));
+            return Ok(());
+        }
+
+        let i = match object.index.get(key.as_str()) {
+            Some(i) => *i,
+            None => {
+                let key = &*self.bump.alloc_str(key);
+                object.index.insert(key, object.entries.len());
+                object.entries.push((key, val));
+                return Ok(());
+            },
+        };
+        match self.duplicate_keys {
+            DuplicateKeys::Error => return Err(ParseError::DuplicateKey(std::mem::take(key))),
+            DuplicateKeys::Collect => {
+                let first = object.entries[i].1;
+                let bump = self.bump;
+                object.collected.entry(i)
+                    .or_insert_with(|| {
+                        let mut values = BumpVec::new_in(bump);
+                        values.push(first);
+                        values
+                    })
+                    .push(val);
+            },
+            DuplicateKeys::TakeFirst | DuplicateKeys::TakeLast => {},
+        }
+        Ok(())
+    }
+
+    fn end_object(&mut self, object: ArenaObject<'b>) -> ArenaValue<'b> {
+        let mut entries = object.entries;
+        for (i, values) in object.collected {
+            entries[i].1 = ArenaValue::Array(values.into_bump_slice());
+        }
+        ArenaValue::Object(entries.into_bump_slice())
+    }
+}
@@ -1,413 +1,5029 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 pub use self::Value::*;
 use std::str::Chars;
 use std::iter::Peekable;
+use std::marker::PhantomData;
 use std::fmt::{Display, Debug, Formatter};
 use std::fmt;
+use std::io::{self, Write};
 use std::ops::Index;
+use std::str::FromStr;
+use std::convert::TryFrom;
+use std::cmp::Ordering;
+use std::slice;
+use std::collections::hash_map;
+use std::borrow::{Borrow, Cow};
+use std::hash::Hash;
+use std::rc::Rc;
+use std::sync::Arc;
 
-#[derive(Clone, PartialEq)]
+#[cfg(feature = "arena")]
+mod arena;
+#[cfg(feature = "arena")]
+pub use arena::{ArenaParser, ArenaValue};
+#[cfg(feature = "arena")]
+pub use bumpalo::Bump;
+
+#[derive(Clone)]
 pub enum Value {
-    Int(i32),
-    Float(f32),
+    Int(i64),
+    Float(f64),
+    // Integers outside of the i64 range, only produced with ParserOptions::big_integers
+    BigInt(i128),
+    // A number together with its literal as written, only produced with
+    // ParserOptions::raw_numbers. Serializing writes raw back unchanged
+    Number {
+        value: f64,
+        raw: String,
+    },
     JsonString(String),
     Array(Vec<Value>),
     Object(HashMap<String, Value>),
     Bool(bool),
     Null,
+    // A subtree that can appear in several places while being stored once, cloning it
+    // only clones the pointer. Reading looks through it transparently, changing it
+    // through a method of Value copies it first if it is still shared elsewhere, see
+    // resolve_mut. Never produced by the parser
+    Shared(Arc<Value>),
+}
+
+// Storage for object keys. Box<str> drops the spare capacity of a String and Rc<str>
+// or Arc<str> make cloning keys cheap
+pub trait Key: Eq + Hash + Clone + Borrow<str> {
+    fn from_string(s: String) -> Self;
+
+    fn as_str(&self) -> &str {
+        self.borrow()
+    }
+}
+
+impl Key for String {
+    fn from_string(s: String) -> Self {
+        s
+    }
+}
+
+impl Key for Box<str> {
+    fn from_string(s: String) -> Self {
+        s.into_boxed_str()
+    }
+}
+
+impl Key for Rc<str> {
+    fn from_string(s: String) -> Self {
+        Rc::from(s)
+    }
+}
+
+impl Key for Arc<str> {
+    fn from_string(s: String) -> Self {
+        Arc::from(s)
+    }
+}
+
+// A Value whose object keys are stored as K, to save memory in documents with many
+// objects. Made with Value::into_keys and turned back with into_value, Shared nodes
+// are copied out on the way in
+#[derive(Clone, PartialEq)]
+pub enum GenericValue<K: Key> {
+    Int(i64),
+    Float(f64),
+    BigInt(i128),
+    Number {
+        value: f64,
+        raw: String,
+    },
+    JsonString(String),
+    Array(Vec<GenericValue<K>>),
+    Object(HashMap<K, GenericValue<K>>),
+    Bool(bool),
+    Null,
+}
+
+impl<K: Key> GenericValue<K> {
+    pub fn get_arr(&self, i: usize) -> Option<&GenericValue<K>> {
+        match self {
+            GenericValue::Array(v) => v.get(i),
+            _ => None,
+        }
+    }
+
+    pub fn get_map(&self, key: &str) -> Option<&GenericValue<K>> {
+        match self {
+            GenericValue::Object(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    // The same tree with String keys again
+    pub fn into_value(self) -> Value {
+        match self {
+            GenericValue::Int(i) => Int(i),
+            GenericValue::Float(fl) => Float(fl),
+            GenericValue::BigInt(b) => BigInt(b),
+            GenericValue::Number { value, raw } => Number { value, raw },
+            GenericValue::JsonString(s) => JsonString(s),
+            GenericValue::Array(v) => Array(v.into_iter().map(GenericValue::into_value).collect()),
+            GenericValue::Object(map) => Object(map.into_iter()
+                .map(|(k, v)| (k.as_str().to_string(), v.into_value()))
+                .collect()),
+            GenericValue::Bool(b) => Bool(b),
+            GenericValue::Null => Null,
+        }
+    }
+
+    pub fn to_writer_with<W: Write>(&self, writer: W, options: &SerializeOptions) -> io::Result<()> {
+        Serializer::new(writer, options.clone()).write_document(self)
+    }
+
+    pub fn to_string_with(&self, options: &SerializeOptions) -> String {
+        self.try_to_string_with(&options.infallible()).unwrap()
+    }
+
+    pub fn try_to_string_with(&self, options: &SerializeOptions) -> io::Result<String> {
+        let mut bytes = Vec::new();
+        self.to_writer_with(&mut bytes, options)?;
+        Ok(String::from_utf8(bytes).unwrap())
+    }
 }
 
 impl Value {
     pub fn get_arr(&self, i: usize) -> Option<&Value> {
-        match self {
+        match self.resolve() {
             Value::Array(v) => v.get(i),
             _ => None,
         }
     }
 
     pub fn get_map(&self, key: &str) -> Option<&Value> {
-        match self {
+        match self.resolve() {
             Value::Object(map) => map.get(key),
             _ => None,
         }
     }
-}
 
-impl Index<&str> for Value {
-    type Output = Value;
+    // An empty object or array with room for n entries or elements, to fill with
+    // insert or push
+    pub fn object_with_capacity(n: usize) -> Value {
+        Object(HashMap::with_capacity(n))
+    }
 
-    fn index(&self, index: &str) -> &Self::Output {
-        match self {
-            Value::Object(map) => &map[index],
-            _ => panic!("{} is not string-indexable", self),
-        }
+    pub fn array_with_capacity(n: usize) -> Value {
+        Array(Vec::with_capacity(n))
     }
-}
 
-impl Index<usize> for Value {
-    type Output = Value;
+    // Wraps val so that clones of the result share it, see Value::Shared
+    pub fn shared(val: Value) -> Value {
+        Shared(Arc::new(val))
+    }
 
-    fn index(&self, index: usize) -> &Self::Output {
+    // A copy that shares whatever is already Shared instead of copying it: O(1) for a
+    // Shared node, while a plain tree is copied down to its Shared children. self is left
+    // as it is, wrap a value with Value::shared up front to make copying all of it cheap
+    pub fn shared_clone(&self) -> Value {
         match self {
-            Value::Array(v) => &v[index],
-            _ => panic!("{} is not integer-indexable", self),
+            Shared(val) => Shared(Arc::clone(val)),
+            val => val.clone(),
         }
     }
-}
 
-impl Display for Value {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    // The node behind any Shared wrappers, self for every other variant
+    pub fn resolve(&self) -> &Value {
         match self {
-            Null => write!(f, "null"),
-            Int(i) => write!(f, "{}", i),
-            Float(fl) => write!(f, "{}", fl),
-            JsonString(j_s) => write!(f, "\"{}\"", j_s),
-            Bool(b) => write!(f, "{}", b),
-            Array(v) => {
-                write!(f, "{:#?}", v)
-            },
-            Object(map) => write!(f, "{:#?}", map),
+            Shared(val) => val.resolve(),
+            val => val,
         }
     }
-}
 
-impl Debug for Value {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        Display::fmt(&self, f)
+    // Like resolve, but a node that is still shared elsewhere is copied first, so that
+    // changes through the result only affect this tree
+    pub fn resolve_mut(&mut self) -> &mut Value {
+        match self {
+            Shared(val) => Arc::make_mut(val).resolve_mut(),
+            val => val,
+        }
     }
-}
 
-#[derive(Debug)]
-pub enum Token {
-    Value(Value), // Only primitive variants of Value are used in Token
-    CurlyBracketOpen,
-    CurlyBracketClose,
-    BracketOpen,
-    BracketClose,
-    Comma,
-    Colon,
-}
+    // Takes the node out of any Shared wrappers, copying it if it is shared elsewhere
+    fn into_unshared(self) -> Value {
+        match self {
+            Shared(val) => Arc::try_unwrap(val).unwrap_or_else(|val| (*val).clone()).into_unshared(),
+            val => val,
+        }
+    }
 
-pub struct Tokenizer<'a> {
-    to_parse: Peekable<Chars<'a>>,
-}
+    // Rebuilds the tree with a different key type, e.g. `value.into_keys::<Box<str>>()`
+    pub fn into_keys<K: Key>(self) -> GenericValue<K> {
+        match self {
+            Int(i) => GenericValue::Int(i),
+            Float(fl) => GenericValue::Float(fl),
+            BigInt(b) => GenericValue::BigInt(b),
+            JsonString(s) => GenericValue::JsonString(s),
+            Array(v) => GenericValue::Array(v.into_iter().map(Value::into_keys).collect()),
+            Object(map) => GenericValue::Object(map.into_iter()
+                .map(|(k, v)| (K::from_string(k), v.into_keys()))
+                .collect()),
+            Number { value, raw } => GenericValue::Number { value, raw },
+            Bool(b) => GenericValue::Bool(b),
+            Null => GenericValue::Null,
+            Shared(val) => Shared(val).into_unshared().into_keys(),
+        }
+    }
 
-impl Tokenizer<'_> {
-    pub fn new(to_parse: &str) -> Tokenizer {
-        Tokenizer {
-            to_parse: to_parse.chars().peekable(),
+    // Keeps only the array elements for which f returns true, anything else is left as is.
+    // f sees elements through any Shared wrappers
+    pub fn retain(&mut self, mut f: impl FnMut(&Value) -> bool) {
+        if let Array(v) = self.resolve_mut() {
+            v.retain(|val| f(val.resolve()));
         }
     }
 
-    pub fn next_token(&mut self) -> Option<Token> {
-        match self.to_parse.peek()? {
-            &'{' => {
-                self.to_parse.next();
-                Some(Token::CurlyBracketOpen)
-            },
-            &'}' => {
-                self.to_parse.next();
-                Some(Token::CurlyBracketClose)
-            },
-            &'[' => {
-                self.to_parse.next();
-                Some(Token::BracketOpen)
-            },
-            &']' => {
-                self.to_parse.next();
-                Some(Token::BracketClose)
-            },
-            &',' => {
-                self.to_parse.next();
-                Some(Token::Comma)
-            },
-            &':' => {
-                self.to_parse.next();
-                Some(Token::Colon)
-            },
-            &'"' => self.next_string(),
-            c if c.is_whitespace() => {
-                self.to_parse.next();
-                self.next_token()
-            },
-            c if *c == 't' => self.next_true(),
-            c if *c == 'f' => self.next_false(),
-            c if *c == 'n' => self.next_null(),
-            '0'..='9' => self.next_number(),
-            c => {
-                println!("Couldn't parse: {}", c);
-                None
-            }
+    // Keeps only the object entries for which f returns true, anything else is left as is
+    pub fn retain_entries(&mut self, mut f: impl FnMut(&str, &Value) -> bool) {
+        if let Object(map) = self.resolve_mut() {
+            map.retain(|key, val| f(key.as_str(), val.resolve()));
         }
     }
 
-    fn next_number(&mut self) -> Option<Token> {
-        let mut found_number = String::new();
+    // A hash of the content that ignores key order and Shared wrappers, so values that
+    // compare equal hash the same. It is computed with a fixed algorithm (64-bit FNV-1a
+    // over a tagged little-endian encoding), so it is stable across runs, platforms and
+    // versions of Rust and can be stored
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET_BASIS;
+        self.hash_content(&mut hash);
+        hash
+    }
+
+    // Counts the nodes of the tree by kind in one traversal
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        self.collect_stats(&mut stats, 0);
+        stats
+    }
 
-        while let Some(c) = self.to_parse.peek() {
-            if !('0'..='9').contains(c) && *c != '.' {
-                break;
-            }
-            found_number.push(self.to_parse.next().unwrap());
+    fn collect_stats(&self, stats: &mut Stats, depth: usize) {
+        match self.resolve() {
+            Null => stats.nulls += 1,
+            Bool(_) => stats.bools += 1,
+            Int(_) | BigInt(_) | Float(_) | Number { .. } => stats.numbers += 1,
+            JsonString(_) => stats.strings += 1,
+            Array(v) => {
+                stats.arrays += 1;
+                stats.max_depth = stats.max_depth.max(depth + 1);
+                for val in v {
+                    val.collect_stats(stats, depth + 1);
+                }
+            },
+            Object(map) => {
+                stats.objects += 1;
+                stats.max_depth = stats.max_depth.max(depth + 1);
+                for val in map.values() {
+                    val.collect_stats(stats, depth + 1);
+                }
+            },
+            Shared(_) => unreachable!("resolve looks through Shared"),
         }
+    }
 
-        if let Ok(i) = found_number.parse::<i32>() {
-            return Some(Token::Value(Int(i)));
-        } else if let Ok(f) = found_number.parse::<f32>() {
-            return Some(Token::Value(Float(f)));
+    fn hash_content(&self, hash: &mut u64) {
+        match self.resolve() {
+            Null => fnv1a(hash, &[0]),
+            Bool(b) => fnv1a(hash, &[1, *b as u8]),
+            Int(i) => {
+                fnv1a(hash, &[2]);
+                fnv1a(hash, &i.to_le_bytes());
+            },
+            BigInt(b) => {
+                fnv1a(hash, &[3]);
+                fnv1a(hash, &b.to_le_bytes());
+            },
+            Float(fl) => {
+                fnv1a(hash, &[4]);
+                // -0.0 == 0.0, so both have to hash the same
+                let fl = if *fl == 0.0 { 0.0 } else { *fl };
+                fnv1a(hash, &fl.to_bits().to_le_bytes());
+            },
+            Number { raw, .. } => {
+                fnv1a(hash, &[8]);
+                hash_str(hash, raw);
+            },
+            JsonString(s) => {
+                fnv1a(hash, &[5]);
+                hash_str(hash, s);
+            },
+            Array(v) => {
+                fnv1a(hash, &[6]);
+                fnv1a(hash, &(v.len() as u64).to_le_bytes());
+                for val in v {
+                    val.hash_content(hash);
+                }
+            },
+            Object(map) => {
+                fnv1a(hash, &[7]);
+                fnv1a(hash, &(map.len() as u64).to_le_bytes());
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by(|x, y| x.0.as_str().cmp(y.0.as_str()));
+                for (key, val) in entries {
+                    hash_str(hash, key.as_str());
+                    val.hash_content(hash);
+                }
+            },
+            Shared(_) => unreachable!("resolve looks through Shared"),
         }
+    }
 
-        None
+    pub fn to_writer<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.to_writer_with(writer, &SerializeOptions::new())
     }
 
-    fn next_true(&mut self) -> Option<Token> {
-        // we know prev char is t
+    pub fn to_writer_pretty<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.to_writer_with(writer, &SerializeOptions::new().pretty(true))
+    }
 
-        let mut failed = false;
+    // Output goes to writer piece by piece while the tree is walked, nothing is buffered
+    // and nothing is flushed here. A slow writer therefore holds up serialization, and an
+    // unbuffered one such as a TcpStream is best wrapped in a BufWriter
+    pub fn to_writer_with<W: Write>(&self, writer: W, options: &SerializeOptions) -> io::Result<()> {
+        Serializer::new(writer, options.clone()).write_document(self)
+    }
 
-        "true".chars().for_each(|c| {
-            if let Some(parsed_c) = self.to_parse.next() {
-                if c != parsed_c {
-                    println!("Couldn't parse true");
-                    failed = true;
-                    return;
-                }
-            } else {
-                println!("Unexpected EOF");
-                failed = true;
-                return;
-            }
-        });
+    // Pretty-prints the value with comments collected by Parser::parse_with_comments
+    pub fn to_writer_with_comments<W: Write>(&self, writer: W, comments: &Comments) -> io::Result<()> {
+        Serializer::with_comments(writer, SerializeOptions::new().pretty(true), comments).write_document(self)
+    }
 
-        if failed {
-            return None;
-        }
+    pub fn to_string_with_comments(&self, comments: &Comments) -> String {
+        let mut bytes = Vec::new();
+        let options = SerializeOptions::new().pretty(true).infallible();
+        // Writing into a Vec<u8> can't fail
+        Serializer::with_comments(&mut bytes, options, comments).write_document(self).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
 
-        Some(Token::Value(Bool(true)))
+    // No whitespace at all outside of string literals, regardless of how Display evolves
+    pub fn to_string_compact(&self) -> String {
+        self.to_string_with(&SerializeOptions::new().pretty(false))
     }
 
-    fn next_false(&mut self) -> Option<Token> {
-        // we know prev char is f
+    // Writes null for a non-finite Float under NonFinite::Error, try_to_string_with
+    // fails instead
+    pub fn to_string_with(&self, options: &SerializeOptions) -> String {
+        // The serializer only ever writes valid UTF-8
+        String::from_utf8(self.to_bytes_with(options)).unwrap()
+    }
 
-        let mut failed = false;
+    pub fn try_to_string_with(&self, options: &SerializeOptions) -> io::Result<String> {
+        let mut bytes = Vec::new();
+        self.to_writer_with(&mut bytes, options)?;
+        // The serializer only ever writes valid UTF-8
+        Ok(String::from_utf8(bytes).unwrap())
+    }
 
-        "false".chars().for_each(|c| {
-            if let Some(parsed_c) = self.to_parse.next() {
-                if c != parsed_c {
-                    println!("Couldn't parse true");
-                    failed = true;
-                    return;
-                }
-            } else {
-                println!("Unexpected EOF");
-                failed = true;
-                return;
-            }
-        });
+    // Writes JSON5 rather than JSON: keys that are identifiers go unquoted, other keys and
+    // strings are single-quoted, and when pretty the last element or entry is followed by
+    // a `,` too. The result is meant for people, most JSON parsers will reject it
+    pub fn to_json5_writer<W: Write>(&self, writer: W, options: &SerializeOptions) -> io::Result<()> {
+        Serializer::json5(writer, options.clone()).write_document(self)
+    }
 
-        if failed {
-            return None;
+    pub fn to_json5_string(&self, options: &SerializeOptions) -> String {
+        let mut bytes = Vec::new();
+        // JSON5 has literals for every float, so this can't fail
+        self.to_json5_writer(&mut bytes, options).unwrap();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    // The contents of a JsonString. Strings are unescaped into the tree while tokenizing,
+    // so this always borrows from the Value, never from the input, and never allocates
+    pub fn as_cow_str(&self) -> Option<Cow<'_, str>> {
+        match self.resolve() {
+            JsonString(s) => Some(Cow::Borrowed(s)),
+            _ => None,
         }
-        Some(Token::Value(Bool(false)))
     }
 
-    fn next_null(&mut self) -> Option<Token> {
-        // we know prev char is n
+    // The literal of a Number, as it appeared in the input
+    pub fn raw_number(&self) -> Option<&str> {
+        match self.resolve() {
+            Number { raw, .. } => Some(raw),
+            _ => None,
+        }
+    }
 
-        let mut failed = false;
+    // Like to_string_with, a non-finite Float is written as null
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_bytes_with(&SerializeOptions::new())
+    }
 
-        "null".chars().for_each(|c| {
-            if let Some(parsed_c) = self.to_parse.next() {
-                if c != parsed_c {
-                    println!("Couldn't parse true");
-                    failed = true;
-                    return;
-                }
-            } else {
-                println!("Unexpected EOF");
-                failed = true;
-                return;
-            }
-        });
+    pub fn to_bytes_pretty(&self) -> Vec<u8> {
+        self.to_bytes_with(&SerializeOptions::new().pretty(true))
+    }
 
-        if failed {
-            return None;
-        }
+    fn to_bytes_with(&self, options: &SerializeOptions) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        // With non-finite floats written as null, nothing can fail when writing into a Vec<u8>
+        self.to_writer_with(&mut bytes, &options.infallible()).unwrap();
+        bytes
+    }
 
-        Some(Token::Value(Null))
+    // The compact serialization as a quoted JSON string literal, for embedding a document
+    // inside another one. Parsing the literal and then its contents gives the value back
+    pub fn to_embedded_string(&self) -> String {
+        escape_json_string(&self.to_string_compact(), true)
     }
 
-    fn next_string(&mut self) -> Option<Token> {
-        // consume "
-        self.to_parse.next();
+    fn type_name(&self) -> &'static str {
+        match self {
+            Int(_) | BigInt(_) => "integer",
+            Float(_) => "float",
+            Number { .. } => "number",
+            JsonString(_) => "string",
+            Array(_) => "array",
+            Object(_) => "object",
+            Bool(_) => "bool",
+            Null => "null",
+            Shared(val) => val.type_name(),
+        }
+    }
 
-        let mut found_str: String = String::new();
-        let mut is_escaped = false;
-        while let Some(c) = self.to_parse.next() {
-            if is_escaped {
-                found_str.push(c);
-                is_escaped = false
-            } else {
-                if c == '\\' {
-                    is_escaped = true;
-                    continue;
-                } else if c == '"' {
-                    break;
-                }
-                found_str.push(c);
-            }
+    // Appends to an array, anything else is a TypeMismatch
+    pub fn push(&mut self, val: Value) -> Result<(), ParseError> {
+        match self.resolve_mut() {
+            Array(v) => {
+                v.push(val);
+                Ok(())
+            },
+            this => mismatch("array", this),
         }
+    }
 
-        Some(Token::Value(JsonString(found_str)))
+    // Adds an entry to an object and returns the value it replaced, anything else is a
+    // TypeMismatch
+    pub fn insert(&mut self, key: impl Into<String>, val: Value) -> Result<Option<Value>, ParseError> {
+        match self.resolve_mut() {
+            Object(map) => Ok(map.insert(key.into(), val)),
+            this => mismatch("object", this),
+        }
     }
-}
 
-impl Iterator for Tokenizer<'_> {
-    type Item = Token;
+    pub fn get_path(&self, segments: &[PathSegment]) -> Option<&Value> {
+        segments.iter().try_fold(self, |val, seg| match seg {
+            PathSegment::Key(key) => val.get_map(key),
+            PathSegment::Index(i) => val.get_arr(*i),
+        })
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next_token()
+    // Looks up a JSON Pointer (RFC 6901) such as "/items/0/name", "" is the value itself
+    pub fn pointer(&self, ptr: &str) -> Option<&Value> {
+        if ptr.is_empty() {
+            return Some(self);
+        }
+
+        ptr.strip_prefix('/')?.split('/').try_fold(self, |val, seg| {
+            let seg = seg.replace("~1", "/").replace("~0", "~");
+            match val.resolve() {
+                Object(map) => map.get(&seg),
+                // Indices are plain digits without leading zeros, "-" (past the end) never resolves
+                Array(v) if seg.bytes().all(|b| b.is_ascii_digit()) && (seg == "0" || !seg.starts_with('0')) => {
+                    v.get(seg.parse::<usize>().ok()?)
+                },
+                _ => None,
+            }
+        })
     }
-}
 
+    // Like pointer, but gives default when ptr doesn't resolve
+    pub fn pointer_or<'a>(&'a self, ptr: &str, default: &'a Value) -> &'a Value {
+        self.pointer(ptr).unwrap_or(default)
+    }
 
-pub struct Parser<'a> {
-    t: Peekable<Tokenizer<'a>>,
-}
+    pub fn get_path_mut(&mut self, segments: &[PathSegment]) -> Option<&mut Value> {
+        segments.iter().try_fold(self, |val, seg| match (seg, val.resolve_mut()) {
+            (PathSegment::Key(key), Object(map)) => map.get_mut(*key),
+            (PathSegment::Index(i), Array(v)) => v.get_mut(*i),
+            _ => None,
+        })
+    }
 
-impl Parser<'_> {
-    pub fn new(input: &str) -> Parser {
-        Parser {
-            t: Tokenizer::new(input).peekable(),
-        }
+    // Checked alternative to the Index operator, returns an error instead of panicking
+    pub fn try_index<I: ValueIndex>(&self, index: I) -> Result<&Value, ParseError> {
+        index.index_into(self)
     }
 
-    pub fn parse(mut self) -> Option<Value> {
-        self.parse_value()
+    // Null-propagating indexing, see OrNull
+    pub fn or_null(&self) -> &OrNull {
+        OrNull::new(self)
     }
 
-    fn parse_object(&mut self) -> Option<Value> {
-        let mut map: HashMap<String, Value> = HashMap::new();
+    // Like try_index, for when the reason doesn't matter, e.g. with a fixed path such as
+    // value.get(("items", 0, "name"))
+    pub fn get<I: ValueIndex>(&self, index: I) -> Option<&Value> {
+        index.index_into(self).ok()
+    }
 
-        // Consume {
-        self.t.next();
+    // A total order over all values: null < bool < number < string < array < object.
+    // Numbers compare numerically across Int, BigInt and Float
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        fn rank(val: &Value) -> u8 {
+            match val {
+                Null => 0,
+                Bool(_) => 1,
+                Int(_) | BigInt(_) | Float(_) | Number { .. } => 2,
+                JsonString(_) => 3,
+                Array(_) => 4,
+                Object(_) => 5,
+                Shared(val) => rank(val),
+            }
+        }
 
-        while let Some(Token::Value(JsonString(_))) = self.t.peek() {
-            match (self.t.next(), self.t.next()) {
-                (Some(Token::Value(JsonString(s))), Some(Token::Colon)) => {
-                    if let Some(val) = self.parse_value() {
-                        map.insert(s, val);
-                        if let Some(&Token::Comma) = self.t.peek() {
-                            self.t.next();
-                            continue;
-                        } else {
-                            break;
-                        }
-                    } else {
-                        println!("Expected Value, got None");
+        match (self.resolve(), other.resolve()) {
+            (Bool(a), Bool(b)) => a.cmp(b),
+            (Int(a), Int(b)) => a.cmp(b),
+            (Int(a), BigInt(b)) => i128::from(*a).cmp(b),
+            (BigInt(a), Int(b)) => a.cmp(&i128::from(*b)),
+            (BigInt(a), BigInt(b)) => a.cmp(b),
+            (Float(a), Float(b)) => a.total_cmp(b),
+            (Float(a), Int(b)) => a.total_cmp(&(*b as f64)),
+            (Float(a), BigInt(b)) => a.total_cmp(&(*b as f64)),
+            (Int(a), Float(b)) => (*a as f64).total_cmp(b),
+            (BigInt(a), Float(b)) => (*a as f64).total_cmp(b),
+            (Number { value: a, .. }, b) if rank(b) == 2 => a.total_cmp(&b.as_f64().unwrap()),
+            (a, Number { value: b, .. }) if rank(a) == 2 => a.as_f64().unwrap().total_cmp(b),
+            (JsonString(a), JsonString(b)) => a.cmp(b),
+            (Array(a), Array(b)) => {
+                a.iter()
+                    .zip(b.iter())
+                    .map(|(x, y)| x.total_cmp(y))
+                    .find(|ord| *ord != Ordering::Equal)
+                    .unwrap_or_else(|| a.len().cmp(&b.len()))
+            },
+            (Object(a), Object(b)) => {
+                let mut a: Vec<_> = a.iter().collect();
+                let mut b: Vec<_> = b.iter().collect();
+                a.sort_by(|x, y| x.0.cmp(y.0));
+                b.sort_by(|x, y| x.0.cmp(y.0));
+                a.iter()
+                    .zip(b.iter())
+                    .map(|((k1, v1), (k2, v2))| k1.cmp(k2).then_with(|| v1.total_cmp(v2)))
+                    .find(|ord| *ord != Ordering::Equal)
+                    .unwrap_or_else(|| a.len().cmp(&b.len()))
+            },
+            (a, b) => rank(a).cmp(&rank(b)),
+        }
+    }
 
-                        break;
+    // Overlays the elements of `other` onto self index by index, appending any extra ones.
+    // Objects at the same position are merged key by key, arrays by index again, and
+    // anything else is replaced by the value from `other`
+    pub fn merge_arrays_by_index(&mut self, other: &Value) {
+        self.merge_with(other, ArrayMergeStrategy::ByIndex);
+    }
+
+    // Deep-merges `other` into self: objects are merged key by key, arrays as chosen by
+    // array_strategy, and anything else is replaced by the value from `other`
+    pub fn merge_with(&mut self, other: &Value, array_strategy: ArrayMergeStrategy) {
+        match (self.resolve_mut(), other.resolve()) {
+            (Array(v), Array(other_v)) => match array_strategy {
+                ArrayMergeStrategy::Replace => *v = other_v.clone(),
+                ArrayMergeStrategy::Concat => v.extend(other_v.iter().cloned()),
+                ArrayMergeStrategy::ByIndex => {
+                    for (i, other_val) in other_v.iter().enumerate() {
+                        match v.get_mut(i) {
+                            Some(val) => val.merge_with(other_val, array_strategy),
+                            None => v.push(other_val.clone()),
+                        }
                     }
                 },
-                (_, Some(tok)) => {
-                    println!("Unexpected Token: {:?}, expected ':'", tok);
-                    return None;
-                },
-                (_, None) => {
-                    println!("Unexpected EOF, expected ':'");
-                    return None;
+            },
+            (Object(map), Object(other_map)) => {
+                for (key, other_val) in other_map {
+                    match map.get_mut(key) {
+                        Some(val) => val.merge_with(other_val, array_strategy),
+                        None => {
+                            map.insert(key.clone(), other_val.clone());
+                        },
+                    }
                 }
-            }
+            },
+            (this, _) => *this = other.clone(),
         }
+    }
 
-        // Consume }
-        match self.t.next() {
-            Some(Token::CurlyBracketClose) => Some(Value::Object(map)),
-            Some(tok) => {
-                println!("Unexpected Token: {:?}, expected '}}'", tok);
-                None
-            }
-            None => {
-                println!("Unexpected EOF");
-                None
-            }
+    // Structural equality where numbers only need to be within epsilon of each other,
+    // e.g. to compare computed floats against expected JSON
+    pub fn approx_eq(&self, other: &Value, epsilon: f64) -> bool {
+        match (self.resolve(), other.resolve()) {
+            (Array(a), Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.approx_eq(y, epsilon))
+            },
+            (Object(a), Object(b)) => {
+                a.len() == b.len() && a.iter().all(|(k, x)| {
+                    b.get(k).is_some_and(|y| x.approx_eq(y, epsilon))
+                })
+            },
+            (a, b) => match (a.as_f64(), b.as_f64()) {
+                (Some(x), Some(y)) => (x - y).abs() <= epsilon,
+                _ => a == b,
+            },
         }
     }
 
-    fn parse_value(&mut self) -> Option<Value> {
-        match self.t.peek()? {
-            Token::CurlyBracketOpen => self.parse_object(),
-            Token::BracketOpen => self.parse_array(),
-            Token::Value(_) => if let Token::Value(val) = self.t.next().unwrap() {
-                Some(val)
-            } else {
-                println!("Something majorly broken, peek returns valid Value but next not??");
-                None
+    // Every place where self and other differ, as the JSON Pointer and the value on each
+    // side, None where one side has no value at all. Arrays are compared by index, object
+    // entries in key order, and anything else is reported as a whole
+    pub fn diff_report(&self, other: &Value) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        diff_into(Some(self), Some(other), &mut Vec::new(), &mut differences);
+        differences
+    }
+
+    // Structural equality that skips object entries named in ignore_keys at any depth, on
+    // both sides, e.g. to compare API responses without their timestamps or generated ids
+    pub fn eq_ignoring(&self, other: &Value, ignore_keys: &[&str]) -> bool {
+        match (self.resolve(), other.resolve()) {
+            (Array(a), Array(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.eq_ignoring(y, ignore_keys))
             },
-            tok => {
-                println!("Unexpected Token: {:?} while trying to parse Value", tok);
-                None
+            (Object(a), Object(b)) => {
+                let ignored = |key: &String| ignore_keys.contains(&key.as_str());
+                let kept = |map: &HashMap<String, Value>| map.keys().filter(|k| !ignored(k)).count();
+                kept(a) == kept(b) && a.iter()
+                    .filter(|(k, _)| !ignored(k))
+                    .all(|(k, x)| b.get(k).is_some_and(|y| x.eq_ignoring(y, ignore_keys)))
             },
-
+            (a, b) => a == b,
         }
     }
 
-    fn parse_array(&mut self) -> Option<Value> {
-        let mut vec: Vec<Value> = Vec::new();
+    // The number as an f64, also accepting numbers quoted as strings such as "1.5".
+    // Strings that only Rust would read as a float, like "inf" or "NaN", are rejected
+    pub fn as_f64_lossy(&self) -> Option<f64> {
+        match self.resolve() {
+            JsonString(s) => s.parse::<f64>().ok().filter(|fl| fl.is_finite()),
+            val => val.as_f64(),
+        }
+    }
 
-        // Consume [
-        self.t.next();
+    // Any number as an f64, integers beyond 2^53 lose precision
+    pub fn as_f64(&self) -> Option<f64> {
+        match self.resolve() {
+            Int(i) => Some(*i as f64),
+            BigInt(b) => Some(*b as f64),
+            Float(fl) | Number { value: fl, .. } => Some(*fl),
+            _ => None,
+        }
+    }
 
-        if let Some(Token::BracketClose) = self.t.peek() {
-            self.t.next();
-            return Some(Array(vec));
+    // Any number as a Float, e.g. to always write `1.0` rather than `1`. Anything else is
+    // returned as is
+    pub fn as_float(&self) -> Value {
+        match self.as_f64() {
+            Some(fl) => Float(fl),
+            None => self.clone(),
         }
+    }
 
-        while let Some(val) = self.parse_value() {
-            vec.push(val);
+    // A number without a fractional part as an Int, None for other numbers, numbers
+    // outside the i64 range and anything that isn't a number
+    pub fn as_int(&self) -> Option<Value> {
+        match self.resolve() {
+            Int(i) => Some(Int(*i)),
+            BigInt(b) => i64::try_from(*b).ok().map(Int),
+            Float(fl) | Number { value: fl, .. } => {
+                // 2^63 itself is already out of range
+                let in_range = *fl >= i64::MIN as f64 && *fl < i64::MAX as f64;
+                Some(Int(*fl as i64)).filter(|_| fl.fract() == 0.0 && in_range)
+            },
+            _ => None,
+        }
+    }
 
-            // Consuming , or ]
-            if let Some(tok) = self.t.next() {
-                match tok {
-                    Token::Comma => continue,
-                    Token::BracketClose => break,
-                    _ => {
-                        println!("Matched something unexpected: {:?}", tok);
-                        return None;
-                    }
-                }
-            } else {
-                println!("Unexpected EOF");
-                return None;
+    // Recursively sorts every array in the tree by total_cmp, keeping equal elements in order
+    pub fn sort_arrays(&mut self) {
+        self.walk_mut(|val| {
+            if let Array(v) = val {
+                v.sort_by(Value::total_cmp);
             }
-        }
+        });
+    }
 
-        Some(Array(vec))
+    pub fn into_typed_vec<T: FromValue>(self) -> Result<Vec<T>, ParseError> {
+        Vec::from_value(self)
     }
-}
 
-// Extending Option<Value> to provide some sugar to work with Value
-pub trait OptionValueExt {
-    fn get_arr(&self, i: usize) -> Option<&Value>;
-    fn get_map(&self, key: &str) -> Option<&Value>;
-}
+    pub fn into_typed_map<T: FromValue>(self) -> Result<HashMap<String, T>, ParseError> {
+        HashMap::from_value(self)
+    }
 
-impl OptionValueExt for Option<&Value> {
-    fn get_arr(&self, i: usize) -> Option<&Value> {
-        match self {
-            Some(val) => {
-                val.get_arr(i)
-            }
-            _ => None
+    // Converts an array of exactly N elements, e.g. `[f64; 3]` coordinates. Each element
+    // is cloned just before it is converted, there is no intermediate copy of the array
+    pub fn to_fixed_array<T: FromValue, const N: usize>(&self) -> Result<[T; N], ParseError> {
+        match self.resolve() {
+            Array(v) => fixed_array(v.len(), v.iter().cloned()),
+            val => mismatch("array", val),
         }
     }
 
-    fn get_map(&self, key: &str) -> Option<&Value> {
-        match self {
-            Some(val) => {
-                val.get_map(key)
-            }
-            _ => None
+    // The counterpart of iterating a Value for objects: drains the entries of an Object
+    // in arbitrary order, every other variant yields nothing
+    pub fn into_entries(self) -> impl Iterator<Item = (String, Value)> {
+        match self.into_unshared() {
+            Object(map) => map.into_iter(),
+            _ => HashMap::new().into_iter(),
+        }
+    }
+
+    // Parses the contents of a JsonString, e.g. an i64 or a date encoded as a string
+    pub fn as_parsed<T: FromStr>(&self) -> Option<T> {
+        match self.resolve() {
+            Value::JsonString(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+
+    // Yields the children of an Array or Object mutably, nothing for scalars
+    pub fn iter_mut(&mut self) -> IterMut<'_> {
+        match self.resolve_mut() {
+            Value::Array(v) => IterMut::Array(v.iter_mut()),
+            Value::Object(map) => IterMut::Object(map.iter_mut()),
+            _ => IterMut::Empty,
+        }
+    }
+
+    // Whether every number in the tree converts to T, e.g. numbers_fit::<i32>() before
+    // handing the data to a system with 32-bit integers. Non-numbers are ignored
+    pub fn numbers_fit<T: FromValue>(&self) -> bool {
+        match self.resolve() {
+            Array(v) => v.iter().all(Value::numbers_fit::<T>),
+            Object(map) => map.values().all(Value::numbers_fit::<T>),
+            val @ (Int(_) | BigInt(_) | Float(_) | Number { .. }) => T::from_value(val.clone()).is_ok(),
+            _ => true,
+        }
+    }
+
+    // Whether this is an array whose elements are all of the same Kind, see element_type.
+    // An empty array qualifies
+    pub fn is_homogeneous_array(&self) -> bool {
+        match self.resolve() {
+            Array(v) => v.is_empty() || self.element_type().is_some(),
+            _ => false,
+        }
+    }
+
+    // The Kind all elements of an array share, None if they differ, there are none or this
+    // isn't an array. Integers and floats are all Kind::Number, numbers_fit tells whether
+    // they convert to a particular type
+    pub fn element_type(&self) -> Option<Kind> {
+        let v = match self.resolve() {
+            Array(v) => v,
+            _ => return None,
+        };
+        let first = v.first()?.kind();
+        v.iter().all(|val| val.kind() == first).then_some(first)
+    }
+
+    pub fn kind(&self) -> Kind {
+        match self.resolve() {
+            Value::Object(_) => Kind::Object,
+            Array(_) => Kind::Array,
+            JsonString(_) => Kind::String,
+            Int(_) | BigInt(_) | Float(_) | Number { .. } => Kind::Number,
+            Bool(_) => Kind::Bool,
+            Null => Kind::Null,
+            Shared(_) => unreachable!("resolve looks through Shared"),
+        }
+    }
+
+    // The JSON Pointer of the first node for which f returns true, visiting each node
+    // before its children. Object entries are visited in no particular order
+    pub fn find_path(&self, f: impl Fn(&Value) -> bool) -> Option<String> {
+        let mut path = Vec::new();
+        if self.find_path_dyn(&f, &mut path) {
+            Some(to_pointer(&path))
+        } else {
+            None
+        }
+    }
+
+    fn find_path_dyn(&self, f: &dyn Fn(&Value) -> bool, path: &mut Vec<String>) -> bool {
+        let node = self.resolve();
+        if f(node) {
+            return true;
+        }
+
+        let children: Box<dyn Iterator<Item = (String, &Value)>> = match node {
+            Array(v) => Box::new(v.iter().enumerate().map(|(i, val)| (i.to_string(), val))),
+            Object(map) => Box::new(map.iter().map(|(key, val)| (key.clone(), val))),
+            _ => return false,
+        };
+        for (segment, child) in children {
+            path.push(segment);
+            if child.find_path_dyn(f, path) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    // Every value stored under `key` in any object of the tree, nested matches included
+    pub fn find_all(&self, key: &str) -> Vec<&Value> {
+        let mut found = Vec::new();
+        self.collect_key(key, &mut found);
+        found
+    }
+
+    pub fn find_first(&self, key: &str) -> Option<&Value> {
+        match self.resolve() {
+            Value::Array(v) => v.iter().find_map(|val| val.find_first(key)),
+            Value::Object(map) => map.get(key)
+                .or_else(|| map.values().find_map(|val| val.find_first(key))),
+            _ => None,
+        }
+    }
+
+    fn collect_key<'a>(&'a self, key: &str, found: &mut Vec<&'a Value>) {
+        match self.resolve() {
+            Value::Array(v) => v.iter().for_each(|val| val.collect_key(key, found)),
+            Value::Object(map) => {
+                for (k, val) in map {
+                    if k == key {
+                        found.push(val);
+                    }
+                    val.collect_key(key, found);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Every distinct object key anywhere in the tree, e.g. to survey the fields of a dataset
+    pub fn all_keys(&self) -> HashSet<String> {
+        let mut keys = HashSet::new();
+        self.collect_keys(&mut keys);
+        keys
+    }
+
+    fn collect_keys(&self, keys: &mut HashSet<String>) {
+        match self.resolve() {
+            Value::Array(v) => v.iter().for_each(|val| val.collect_keys(keys)),
+            Value::Object(map) => {
+                for (k, val) in map {
+                    if !keys.contains(k) {
+                        keys.insert(k.clone());
+                    }
+                    val.collect_keys(keys);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Returns the only element of a one-element array, anything else is returned as is
+    pub fn unwrap_array_singleton(self) -> Value {
+        match self {
+            Value::Array(mut v) if v.len() == 1 => v.pop().unwrap(),
+            Shared(val) => {
+                let single = match val.resolve() {
+                    Array(v) if v.len() == 1 => Some(v[0].clone()),
+                    _ => None,
+                };
+                single.unwrap_or(Shared(val))
+            },
+            val => val,
+        }
+    }
+
+    // The elements of an array in range, None if it is not an array or the range doesn't
+    // lie within it
+    pub fn slice(&self, range: std::ops::Range<usize>) -> Option<&[Value]> {
+        match self.resolve() {
+            Array(v) => v.get(range),
+            _ => None,
+        }
+    }
+
+    // For fields that hold either a list or a single item: the elements of an array, a
+    // scalar or object as the only element, and nothing for null
+    pub fn coerce_to_array(&self) -> Vec<Value> {
+        self.coerce_to_array_with(false)
+    }
+
+    // Like coerce_to_array, but with keep_null set a null becomes [null] like any scalar
+    pub fn coerce_to_array_with(&self, keep_null: bool) -> Vec<Value> {
+        match self.resolve() {
+            Array(v) => v.clone(),
+            Null if !keep_null => Vec::new(),
+            val => vec![val.clone()],
+        }
+    }
+
+    // The entries of an object, anything else gives an empty map
+    pub fn coerce_to_object(&self) -> HashMap<String, Value> {
+        match self.resolve() {
+            Object(map) => map.clone(),
+            _ => HashMap::new(),
+        }
+    }
+
+    // Encodes an object of scalars as key=value pairs joined by &, in key order. Strings
+    // go in without quotes, null as an empty value, and anything else as its JSON. None
+    // for anything but an object, or if any value is an array, an object or non-finite
+    pub fn to_query_string(&self) -> Option<String> {
+        let map = match self.resolve() {
+            Object(map) => map,
+            _ => return None,
+        };
+
+        let mut pairs = Vec::with_capacity(map.len());
+        for (key, val) in map {
+            let val = match val.resolve() {
+                Array(_) | Object(_) => return None,
+                JsonString(s) => percent_encode(s),
+                Null => String::new(),
+                val => val.try_to_string_with(&SerializeOptions::new()).ok()?,
+            };
+            pairs.push(format!("{}={}", percent_encode(key), val));
+        }
+        pairs.sort();
+        Some(pairs.join("&"))
+    }
+
+    // A copy of the entries of an object that iterates in key order
+    pub fn to_btree_map(&self) -> Option<BTreeMap<String, Value>> {
+        match self.resolve() {
+            Object(map) => Some(map.iter().map(|(k, v)| (k.clone(), v.clone())).collect()),
+            _ => None,
+        }
+    }
+
+    // Visits every node of the tree, children before their parent, so f may
+    // replace a node without the replacement being visited again. f is given the nodes
+    // behind Shared wrappers, copied first where still shared elsewhere
+    pub fn walk_mut(&mut self, mut f: impl FnMut(&mut Value)) {
+        self.walk_mut_dyn(&mut f);
+    }
+
+    fn walk_mut_dyn(&mut self, f: &mut dyn FnMut(&mut Value)) {
+        let node = self.resolve_mut();
+        for child in node.iter_mut() {
+            match child {
+                ChildMut::Element(val) | ChildMut::Entry(_, val) => val.walk_mut_dyn(f),
+            }
+        }
+        f(node);
+    }
+
+    pub fn replace_matching(&mut self, predicate: impl Fn(&Value) -> bool, replacement: Value) {
+        self.walk_mut(|val| {
+            if predicate(val) {
+                *val = replacement.clone();
+            }
+        });
+    }
+
+    // Replaces every JsonString in the tree by f of it, with keys set also every object
+    // key. Keys that f maps to the same string collapse into a single entry
+    pub fn map_strings(&mut self, keys: bool, mut f: impl FnMut(&str) -> String) {
+        self.walk_mut(|val| match val {
+            JsonString(s) => *s = f(s),
+            Object(map) if keys => {
+                *map = map.drain().map(|(key, child)| (f(&key), child)).collect();
+            },
+            _ => {},
+        });
+    }
+
+    // Cuts every JsonString longer than max_len chars down to its first max_len chars
+    // followed by `…`, e.g. to keep logged documents bounded. Keys are left as they are
+    pub fn truncate_strings(&mut self, max_len: usize) {
+        self.map_strings(false, |s| match s.char_indices().nth(max_len) {
+            Some((end, _)) => format!("{}…", &s[..end]),
+            None => s.to_string(),
+        });
+    }
+
+    // Removes every object entry whose value is null, throughout the tree. With in_arrays,
+    // null array elements are removed as well. Containers emptied this way are kept
+    pub fn prune_nulls(&mut self, in_arrays: bool) {
+        self.walk_mut(|val| {
+            val.retain_entries(|_, child| !matches!(child, Null));
+            if in_arrays {
+                val.retain(|child| !matches!(child, Null));
+            }
+        });
+    }
+
+    // Turns every object whose keys are exactly "0".."n-1" into an array in index order,
+    // throughout the tree. Empty objects and keys like "01" or "+1" are left alone
+    pub fn objects_to_arrays(&mut self) {
+        self.walk_mut(|val| {
+            if let Object(map) = val {
+                let len = map.len();
+                let is_index = |key: &String| key.parse::<usize>().is_ok_and(|i| i < len && i.to_string() == *key);
+                if len == 0 || !map.keys().all(is_index) {
+                    return;
+                }
+                let mut entries: Vec<(usize, Value)> = map.drain()
+                    .map(|(key, child)| (key.parse().unwrap(), child))
+                    .collect();
+                entries.sort_unstable_by_key(|(i, _)| *i);
+                *val = Array(entries.into_iter().map(|(_, child)| child).collect());
+            }
+        });
+    }
+}
+
+// How Value::merge_with combines two arrays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayMergeStrategy {
+    // The array from `other` wins
+    Replace,
+    // The elements of `other` are appended
+    Concat,
+    // Elements at the same index are merged, extra ones appended
+    ByIndex,
+}
+
+#[derive(Debug)]
+pub enum ChildMut<'a> {
+    Element(&'a mut Value),
+    Entry(&'a String, &'a mut Value),
+}
+
+pub enum IterMut<'a> {
+    Array(slice::IterMut<'a, Value>),
+    Object(hash_map::IterMut<'a, String, Value>),
+    Empty,
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = ChildMut<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            IterMut::Array(it) => it.next().map(ChildMut::Element),
+            IterMut::Object(it) => it.next().map(|(k, v)| ChildMut::Entry(k, v)),
+            IterMut::Empty => None,
+        }
+    }
+}
+
+// Iterating a Value walks the elements of an Array. Every other variant, Object
+// included, yields nothing rather than panicking
+impl IntoIterator for Value {
+    type Item = Value;
+    type IntoIter = std::vec::IntoIter<Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self.into_unshared() {
+            Value::Array(v) => v.into_iter(),
+            _ => Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Value {
+    type Item = &'a Value;
+    type IntoIter = slice::Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self.resolve() {
+            Value::Array(v) => v.iter(),
+            _ => [].iter(),
+        }
+    }
+}
+
+// One step of a path built at runtime, see Value::get_path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSegment<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+// Anything that can look up a child of a Value: string keys for objects, usize for arrays
+pub trait ValueIndex {
+    fn index_into<'v>(&self, value: &'v Value) -> Result<&'v Value, ParseError>;
+}
+
+impl ValueIndex for str {
+    fn index_into<'v>(&self, value: &'v Value) -> Result<&'v Value, ParseError> {
+        match value.resolve() {
+            Object(map) => map.get(self).ok_or_else(|| ParseError::KeyNotFound(self.to_string())),
+            val => mismatch("object", val),
+        }
+    }
+}
+
+impl ValueIndex for String {
+    fn index_into<'v>(&self, value: &'v Value) -> Result<&'v Value, ParseError> {
+        self.as_str().index_into(value)
+    }
+}
+
+impl ValueIndex for usize {
+    fn index_into<'v>(&self, value: &'v Value) -> Result<&'v Value, ParseError> {
+        match value.resolve() {
+            Array(v) => v.get(*self).ok_or(ParseError::IndexOutOfBounds {
+                index: *self,
+                len: v.len(),
+            }),
+            val => mismatch("array", val),
+        }
+    }
+}
+
+impl<T: ValueIndex + ?Sized> ValueIndex for &T {
+    fn index_into<'v>(&self, value: &'v Value) -> Result<&'v Value, ParseError> {
+        (**self).index_into(value)
+    }
+}
+
+// Tuples index one element after the other, so mixed paths are checked at compile time
+macro_rules! tuple_value_index {
+    ($($name:ident)+) => {
+        impl<$($name: ValueIndex),+> ValueIndex for ($($name,)+) {
+            fn index_into<'v>(&self, value: &'v Value) -> Result<&'v Value, ParseError> {
+                #[allow(non_snake_case)]
+                let ($($name,)+) = self;
+                let val = value;
+                $(let val = $name.index_into(val)?;)+
+                Ok(val)
+            }
+        }
+
+        impl<$($name: ValueIndex),+> Index<($($name,)+)> for Value {
+            type Output = Value;
+
+            fn index(&self, index: ($($name,)+)) -> &Self::Output {
+                match index.index_into(self) {
+                    Ok(val) => val,
+                    Err(e) => panic!("{}", e),
+                }
+            }
+        }
+    };
+}
+
+tuple_value_index!(A);
+tuple_value_index!(A B);
+tuple_value_index!(A B C);
+tuple_value_index!(A B C D);
+tuple_value_index!(A B C D E);
+tuple_value_index!(A B C D E F);
+
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, index: &str) -> &Self::Output {
+        match self.resolve() {
+            Value::Object(map) => &map[index],
+            _ => panic!("{} is not string-indexable", self),
+        }
+    }
+}
+
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match self.resolve() {
+            Value::Array(v) => &v[index],
+            _ => panic!("{} is not integer-indexable", self),
+        }
+    }
+}
+
+// A view of a Value whose Index never panics: a missing key or index, or indexing into
+// the wrong kind of value, gives Null, so v.or_null()["a"][0]["b"] is always safe
+#[repr(transparent)]
+pub struct OrNull(Value);
+
+impl OrNull {
+    fn new(val: &Value) -> &OrNull {
+        // SAFETY: OrNull is a repr(transparent) wrapper of Value, so both references
+        // have the same layout and the lifetime is carried over
+        unsafe { &*(val as *const Value as *const OrNull) }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl std::ops::Deref for OrNull {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl Index<&str> for OrNull {
+    type Output = OrNull;
+
+    fn index(&self, index: &str) -> &Self::Output {
+        OrNull::new(self.0.get_map(index).unwrap_or(&NULL))
+    }
+}
+
+impl Index<usize> for OrNull {
+    type Output = OrNull;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        OrNull::new(self.0.get_arr(index).unwrap_or(&NULL))
+    }
+}
+
+impl Debug for OrNull {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+// Shared nodes compare equal to what they point to
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self.resolve(), other.resolve()) {
+            (Int(a), Int(b)) => a == b,
+            (Float(a), Float(b)) => a == b,
+            (BigInt(a), BigInt(b)) => a == b,
+            (Number { raw: a, .. }, Number { raw: b, .. }) => a == b,
+            (JsonString(a), JsonString(b)) => a == b,
+            (Array(a), Array(b)) => a == b,
+            (Object(a), Object(b)) => a == b,
+            (Bool(a), Bool(b)) => a == b,
+            (Null, Null) => true,
+            _ => false,
+        }
+    }
+}
+
+// Comparing against native values, mismatched variants are never equal
+impl PartialEq<i64> for Value {
+    fn eq(&self, other: &i64) -> bool {
+        match self.resolve() {
+            Int(i) => i == other,
+            BigInt(b) => *b == i128::from(*other),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        match self.resolve() {
+            Float(fl) | Number { value: fl, .. } => fl == other,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<bool> for Value {
+    fn eq(&self, other: &bool) -> bool {
+        match self.resolve() {
+            Bool(b) => b == other,
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq<&str> for Value {
+    fn eq(&self, other: &&str) -> bool {
+        match self.resolve() {
+            JsonString(j_s) => j_s == other,
+            _ => false,
+        }
+    }
+}
+
+// Compact JSON, or pretty-printed with the alternate flag, i.e. {:#}
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_with(&SerializeOptions::new().pretty(f.alternate())))
+    }
+}
+
+// Like Display, except that non-finite floats are shown as strings instead of failing
+impl Debug for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let options = SerializeOptions::new().pretty(f.alternate()).non_finite(NonFinite::String);
+        f.write_str(&self.to_string_with(&options))
+    }
+}
+
+impl<K: Key> Display for GenericValue<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_string_with(&SerializeOptions::new().pretty(f.alternate())))
+    }
+}
+
+impl<K: Key> Debug for GenericValue<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let options = SerializeOptions::new().pretty(f.alternate()).non_finite(NonFinite::String);
+        f.write_str(&self.to_string_with(&options))
+    }
+}
+
+// Conversions from strings parse a whole document, trailing input is an error
+impl FromStr for Value {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s).parse_document()
+    }
+}
+
+impl TryFrom<&str> for Value {
+    type Error = ParseError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<String> for Value {
+    type Error = ParseError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+// The default is AlwaysDecimalPoint so that a serialized Float never reads back as an Int
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FloatFormat {
+    // Fewest digits that still parse back to the same float, with an exponent where that
+    // is shorter, e.g. 1e300 rather than 1 and 300 zeros
+    Shortest,
+    // Exactly n digits after the decimal point
+    FixedDecimal(usize),
+    // Like Shortest, but always contains a decimal point or exponent. An exponent is only
+    // used where JavaScript does, for magnitudes of at least 1e21 or below 1e-6
+    #[default]
+    AlwaysDecimalPoint,
+}
+
+// What to write for a Float that is NaN or infinite, which JSON has no literal for
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NonFinite {
+    // Fail with an io::Error of kind InvalidData. Where serializing can't fail, i.e.
+    // Display and the to_string and to_bytes methods, null is written instead, like
+    // JavaScript's JSON.stringify does
+    #[default]
+    Error,
+    Null,
+    // The strings "NaN", "Infinity" and "-Infinity"
+    String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SerializeOptions {
+    pretty: bool,
+    float_format: FloatFormat,
+    align_colons: bool,
+    skip_empty: bool,
+    non_finite: NonFinite,
+    ascii_only: bool,
+    // Spaces per level when pretty, None for 2
+    indent: Option<usize>,
+    trailing_newline: bool,
+}
+
+impl SerializeOptions {
+    pub fn new() -> SerializeOptions {
+        SerializeOptions::default()
+    }
+
+    pub fn pretty(mut self, pretty: bool) -> SerializeOptions {
+        self.pretty = pretty;
+        self
+    }
+
+    pub fn float_format(mut self, float_format: FloatFormat) -> SerializeOptions {
+        self.float_format = float_format;
+        self
+    }
+
+    // In pretty mode, pads the keys of each object so that their colons line up
+    pub fn align_colons(mut self, align_colons: bool) -> SerializeOptions {
+        self.align_colons = align_colons;
+        self
+    }
+
+    // Leaves out object entries whose value is an empty array or object, or an object
+    // that is empty once such entries are left out. The output then no longer parses back
+    // to an equal Value. Array elements and the top-level value are always written, as
+    // dropping them would shift indices
+    pub fn skip_empty(mut self, skip_empty: bool) -> SerializeOptions {
+        self.skip_empty = skip_empty;
+        self
+    }
+
+    // Ignored for JSON5, which has literals for all of them
+    pub fn non_finite(mut self, non_finite: NonFinite) -> SerializeOptions {
+        self.non_finite = non_finite;
+        self
+    }
+
+    pub fn indent(mut self, indent: usize) -> SerializeOptions {
+        self.indent = Some(indent);
+        self
+    }
+
+    // Ends the output with a \n, as files usually do
+    pub fn trailing_newline(mut self, trailing_newline: bool) -> SerializeOptions {
+        self.trailing_newline = trailing_newline;
+        self
+    }
+
+    // Escapes every non-ASCII char in strings and keys as \uXXXX, for consumers that
+    // mangle anything but ASCII
+    pub fn ascii_only(mut self, ascii_only: bool) -> SerializeOptions {
+        self.ascii_only = ascii_only;
+        self
+    }
+
+    // The options for a serialization that can't fail, see NonFinite::Error
+    fn infallible(&self) -> SerializeOptions {
+        let mut options = self.clone();
+        if options.non_finite == NonFinite::Error {
+            options.non_finite = NonFinite::Null;
+        }
+        options
+    }
+}
+
+// Escapes s the way the serializer writes strings, surrounded by quotes if quoted is set.
+// Escapes are only used where JSON requires them: quotes, backslashes and control chars
+pub fn escape_json_string(s: &str, quoted: bool) -> String {
+    escape_string(s, '"', quoted, false)
+}
+
+// Like escape_json_string, but with quote as the quote char that needs escaping. With
+// ascii_only everything beyond ASCII is escaped too, as a surrogate pair if need be
+fn escape_string(s: &str, quote: char, quoted: bool, ascii_only: bool) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    if quoted {
+        escaped.push(quote);
+    }
+    for c in s.chars() {
+        match c {
+            c if c == quote => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{08}' => escaped.push_str("\\b"),
+            '\u{0C}' => escaped.push_str("\\f"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c if ascii_only && !c.is_ascii() => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    escaped.push_str(&format!("\\u{:04x}", unit));
+                }
+            },
+            c => escaped.push(c),
+        }
+    }
+    if quoted {
+        escaped.push(quote);
+    }
+    escaped
+}
+
+// Escapes all but the unreserved chars of RFC 3986 as %XX of their UTF-8 bytes
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Keys that JSON5 allows without quotes, restricted to ASCII identifiers
+fn is_json5_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {},
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+// Node counts from Value::stats. max_depth is the deepest nesting of arrays and objects,
+// counted like ParserOptions::max_depth: 0 for a scalar, 1 for a flat array
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub objects: usize,
+    pub arrays: usize,
+    pub strings: usize,
+    pub numbers: usize,
+    pub bools: usize,
+    pub nulls: usize,
+    pub max_depth: usize,
+}
+
+// One entry of Value::diff_report. Displays as e.g. `at /a/b: expected 1, got 2`, with
+// self as the expected side
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub path: String,
+    pub left: Option<Value>,
+    pub right: Option<Value>,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let show = |val: &Option<Value>| match val {
+            Some(val) => format!("{:?}", val),
+            None => String::from("nothing"),
+        };
+        let path = if self.path.is_empty() { "the root" } else { &self.path };
+        write!(f, "at {}: expected {}, got {}", path, show(&self.left), show(&self.right))
+    }
+}
+
+fn diff_into(left: Option<&Value>, right: Option<&Value>, path: &mut Vec<String>, differences: &mut Vec<Difference>) {
+    match (left.map(Value::resolve), right.map(Value::resolve)) {
+        (Some(Array(a)), Some(Array(b))) => {
+            for i in 0..a.len().max(b.len()) {
+                path.push(i.to_string());
+                diff_into(a.get(i), b.get(i), path, differences);
+                path.pop();
+            }
+        },
+        (Some(Object(a)), Some(Object(b))) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys().filter(|key| !a.contains_key(*key))).collect();
+            keys.sort();
+            for key in keys {
+                path.push(key.clone());
+                diff_into(a.get(key), b.get(key), path, differences);
+                path.pop();
+            }
+        },
+        (a, b) if a == b => {},
+        (a, b) => differences.push(Difference {
+            path: to_pointer(path),
+            left: a.cloned(),
+            right: b.cloned(),
+        }),
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(hash: &mut u64, bytes: &[u8]) {
+    for byte in bytes {
+        *hash ^= u64::from(*byte);
+        *hash = hash.wrapping_mul(FNV_PRIME);
+    }
+}
+
+// Length-prefixed, so that adjacent strings can't run into each other
+fn hash_str(hash: &mut u64, s: &str) {
+    fnv1a(hash, &(s.len() as u64).to_le_bytes());
+    fnv1a(hash, s.as_bytes());
+}
+
+// A tree the serializer can write, i.e. a Value or a GenericValue
+trait Node: Sized {
+    fn node(&self) -> NodeRef<'_, Self>;
+}
+
+// One node of such a tree, with Shared wrappers already looked through
+enum NodeRef<'v, T> {
+    Null,
+    Bool(bool),
+    Int(i64),
+    BigInt(i128),
+    Float(f64),
+    // A number literal, written back as is
+    Raw(&'v str),
+    String(&'v str),
+    Array(&'v [T]),
+    Object(Vec<(&'v str, &'v T)>),
+}
+
+impl Node for Value {
+    fn node(&self) -> NodeRef<'_, Value> {
+        match self.resolve() {
+            Null => NodeRef::Null,
+            Bool(b) => NodeRef::Bool(*b),
+            Int(i) => NodeRef::Int(*i),
+            BigInt(b) => NodeRef::BigInt(*b),
+            Float(fl) => NodeRef::Float(*fl),
+            Number { raw, .. } => NodeRef::Raw(raw),
+            JsonString(s) => NodeRef::String(s),
+            Array(v) => NodeRef::Array(v),
+            Object(map) => NodeRef::Object(map.iter().map(|(key, val)| (key.as_str(), val)).collect()),
+            Shared(_) => unreachable!("resolve looks through Shared"),
+        }
+    }
+}
+
+impl<K: Key> Node for GenericValue<K> {
+    fn node(&self) -> NodeRef<'_, GenericValue<K>> {
+        match self {
+            GenericValue::Null => NodeRef::Null,
+            GenericValue::Bool(b) => NodeRef::Bool(*b),
+            GenericValue::Int(i) => NodeRef::Int(*i),
+            GenericValue::BigInt(b) => NodeRef::BigInt(*b),
+            GenericValue::Float(fl) => NodeRef::Float(*fl),
+            GenericValue::Number { raw, .. } => NodeRef::Raw(raw),
+            GenericValue::JsonString(s) => NodeRef::String(s),
+            GenericValue::Array(v) => NodeRef::Array(v),
+            GenericValue::Object(map) => NodeRef::Object(map.iter().map(|(key, val)| (key.as_str(), val)).collect()),
+        }
+    }
+}
+
+// Whether skip_empty leaves the value out as an object entry
+fn is_empty_container<T: Node>(val: &T) -> bool {
+    match val.node() {
+        NodeRef::Array(v) => v.is_empty(),
+        NodeRef::Object(entries) => entries.iter().all(|(_, val)| is_empty_container(*val)),
+        _ => false,
+    }
+}
+
+// Writes valid JSON, either compact or pretty-printed with two spaces per level
+struct Serializer<'c, W: Write> {
+    writer: W,
+    options: SerializeOptions,
+    level: usize,
+    // Comments to re-emit and the path of the value currently being written
+    comments: Option<&'c Comments>,
+    path: Vec<String>,
+    // Write JSON5 instead, see Value::to_json5_writer
+    json5: bool,
+}
+
+impl<'c, W: Write> Serializer<'c, W> {
+    fn new(writer: W, options: SerializeOptions) -> Serializer<'c, W> {
+        Serializer {
+            writer,
+            options,
+            level: 0,
+            comments: None,
+            path: Vec::new(),
+            json5: false,
+        }
+    }
+
+    fn json5(writer: W, options: SerializeOptions) -> Serializer<'c, W> {
+        Serializer {
+            json5: true,
+            ..Serializer::new(writer, options)
+        }
+    }
+
+    fn with_comments(writer: W, options: SerializeOptions, comments: &'c Comments) -> Serializer<'c, W> {
+        Serializer {
+            comments: Some(comments),
+            ..Serializer::new(writer, options)
+        }
+    }
+
+    fn write_document<T: Node>(&mut self, value: &T) -> io::Result<()> {
+        self.write_leading_comments()?;
+        self.write_value(value)?;
+        self.write_trailing_comments()?;
+        if self.options.trailing_newline {
+            writeln!(self.writer)?;
+        }
+        Ok(())
+    }
+
+    fn write_value<T: Node>(&mut self, value: &T) -> io::Result<()> {
+        match value.node() {
+            NodeRef::Null => write!(self.writer, "null"),
+            NodeRef::Int(i) => write!(self.writer, "{}", i),
+            NodeRef::BigInt(b) => write!(self.writer, "{}", b),
+            NodeRef::Float(fl) => self.write_float(fl),
+            NodeRef::String(j_s) => self.write_string(j_s),
+            NodeRef::Raw(raw) => write!(self.writer, "{}", raw),
+            NodeRef::Bool(b) => write!(self.writer, "{}", b),
+            NodeRef::Array(v) => {
+                if v.is_empty() {
+                    return write!(self.writer, "[]");
+                }
+
+                write!(self.writer, "[")?;
+                self.level += 1;
+                for (i, val) in v.iter().enumerate() {
+                    self.write_newline()?;
+                    self.enter(|| i.to_string());
+                    self.write_leading_comments()?;
+                    self.write_value(val)?;
+                    if i + 1 < v.len() || self.trailing_commas() {
+                        write!(self.writer, ",")?;
+                    }
+                    self.write_trailing_comments()?;
+                    self.leave();
+                }
+                self.level -= 1;
+                self.write_newline()?;
+                write!(self.writer, "]")
+            },
+            NodeRef::Object(mut entries) => {
+                if self.options.skip_empty {
+                    entries.retain(|(_, val)| !is_empty_container(*val));
+                }
+                if entries.is_empty() {
+                    return write!(self.writer, "{{}}");
+                }
+
+                let key_width = if self.options.pretty && self.options.align_colons {
+                    entries.iter().map(|(key, _)| self.key_width(key)).max().unwrap_or(0)
+                } else {
+                    0
+                };
+
+                write!(self.writer, "{{")?;
+                self.level += 1;
+                for (i, (key, val)) in entries.iter().enumerate() {
+                    self.write_newline()?;
+                    self.enter(|| key.to_string());
+                    self.write_leading_comments()?;
+                    self.write_key(key)?;
+                    if key_width > 0 {
+                        let padding = key_width - self.key_width(key);
+                        write!(self.writer, "{}", " ".repeat(padding))?;
+                    }
+                    write!(self.writer, ":")?;
+                    if self.options.pretty {
+                        write!(self.writer, " ")?;
+                    }
+                    self.write_value(*val)?;
+                    if i + 1 < entries.len() || self.trailing_commas() {
+                        write!(self.writer, ",")?;
+                    }
+                    self.write_trailing_comments()?;
+                    self.leave();
+                }
+                self.level -= 1;
+                self.write_newline()?;
+                write!(self.writer, "}}")
+            },
+        }
+    }
+
+    fn write_float(&mut self, fl: f64) -> io::Result<()> {
+        if !fl.is_finite() {
+            let literal = if fl.is_nan() { "NaN" } else if fl > 0.0 { "Infinity" } else { "-Infinity" };
+            return match self.options.non_finite {
+                _ if self.json5 => write!(self.writer, "{}", literal),
+                NonFinite::Error => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} can't be represented in JSON", literal),
+                )),
+                NonFinite::Null => write!(self.writer, "null"),
+                NonFinite::String => self.write_string(literal),
+            };
+        }
+
+        // Display never uses an exponent, `{:e}` always does
+        let plain = fl.to_string();
+        let exponent = format!("{:e}", fl);
+        match self.options.float_format {
+            FloatFormat::FixedDecimal(n) => write!(self.writer, "{:.*}", n, fl),
+            FloatFormat::Shortest if exponent.len() < plain.len() => write!(self.writer, "{}", exponent),
+            FloatFormat::Shortest => write!(self.writer, "{}", plain),
+            FloatFormat::AlwaysDecimalPoint if fl != 0.0 && !(1e-6..1e21).contains(&fl.abs()) => {
+                write!(self.writer, "{}", exponent)
+            },
+            FloatFormat::AlwaysDecimalPoint if !plain.contains('.') => write!(self.writer, "{}.0", plain),
+            FloatFormat::AlwaysDecimalPoint => write!(self.writer, "{}", plain),
+        }
+    }
+
+    fn write_string(&mut self, s: &str) -> io::Result<()> {
+        self.writer.write_all(self.quote(s).as_bytes())
+    }
+
+    fn quote(&self, s: &str) -> String {
+        let quote = if self.json5 { '\'' } else { '"' };
+        escape_string(s, quote, true, self.options.ascii_only)
+    }
+
+    fn write_key(&mut self, key: &str) -> io::Result<()> {
+        if self.json5 && is_json5_identifier(key) {
+            write!(self.writer, "{}", key)
+        } else {
+            self.write_string(key)
+        }
+    }
+
+    // Number of chars key takes up as written by write_key
+    fn key_width(&self, key: &str) -> usize {
+        if self.json5 && is_json5_identifier(key) {
+            key.len()
+        } else {
+            self.quote(key).chars().count()
+        }
+    }
+
+    // Only when pretty, in compact output they would just be noise
+    fn trailing_commas(&self) -> bool {
+        self.json5 && self.options.pretty
+    }
+
+    // Path tracking is only needed to look up comments
+    fn enter(&mut self, segment: impl FnOnce() -> String) {
+        if self.comments.is_some() {
+            self.path.push(segment());
+        }
+    }
+
+    fn leave(&mut self) {
+        self.path.pop();
+    }
+
+    fn current_comments(&self) -> Option<&'c ValueComments> {
+        self.comments?.get(&to_pointer(&self.path))
+    }
+
+    // Each leading comment goes on its own line before the value
+    fn write_leading_comments(&mut self) -> io::Result<()> {
+        if let Some(comments) = self.current_comments() {
+            for comment in &comments.leading {
+                write!(self.writer, "{}", comment)?;
+                self.write_newline()?;
+            }
+        }
+        Ok(())
+    }
+
+    // Trailing comments stay on the line of the value, after its `,`
+    fn write_trailing_comments(&mut self) -> io::Result<()> {
+        if let Some(comments) = self.current_comments() {
+            for comment in &comments.trailing {
+                write!(self.writer, " {}", comment)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Only emits anything in pretty mode
+    fn write_newline(&mut self) -> io::Result<()> {
+        if self.options.pretty {
+            let indent = self.options.indent.unwrap_or(2);
+            write!(self.writer, "\n{}", " ".repeat(indent * self.level))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    KeyNotFound(String),
+    IndexOutOfBounds {
+        index: usize,
+        len: usize,
+    },
+    // An array of the wrong length for a fixed-size target
+    LengthMismatch {
+        expected: usize,
+        found: usize,
+    },
+    // A number that doesn't fit into the requested integer type
+    OutOfRange {
+        target: &'static str,
+    },
+    // Byte offset of the first byte that isn't valid UTF-8
+    InvalidUtf8 {
+        offset: usize,
+    },
+    UnexpectedToken {
+        found: String,
+        expected: String,
+    },
+    UnexpectedEof {
+        expected: String,
+    },
+    UnexpectedChar(char),
+    InvalidLiteral(&'static str),
+    InvalidNumber(String),
+    UnterminatedString,
+    UnterminatedComment,
+    InvalidEscape(String),
+    MaxDepthExceeded(usize),
+    // The input needed more tokens than ParserOptions::max_tokens allows
+    BudgetExceeded(usize),
+    DuplicateKey(String),
+    InvalidSchema(String),
+    // Another error together with the JSON Pointer of the value that caused it
+    AtPath {
+        error: Box<ParseError>,
+        path: String,
+    },
+    // Another error together with where in the input it happened: the char offset and
+    // a snippet of the input around it, with the caret column inside the snippet
+    Located {
+        error: Box<ParseError>,
+        offset: usize,
+        snippet: String,
+        caret: usize,
+    },
+}
+
+impl ParseError {
+    // The error without any location or path attached
+    pub fn root(&self) -> &ParseError {
+        match self {
+            ParseError::Located { error, .. } | ParseError::AtPath { error, .. } => error.root(),
+            e => e,
+        }
+    }
+
+    // Attaches the chars of `input` around the char offset `offset`
+    fn located(self, input: &str, offset: usize) -> ParseError {
+        const CONTEXT: usize = 10;
+
+        let start = offset.saturating_sub(CONTEXT);
+        // Any whitespace would shift the caret, so it is all shown as a plain space
+        let snippet = input.chars()
+            .skip(start)
+            .take(2 * CONTEXT)
+            .map(|c| if c.is_whitespace() || c.is_control() { ' ' } else { c })
+            .collect();
+
+        ParseError::Located {
+            error: Box::new(self),
+            offset,
+            snippet,
+            caret: offset - start,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TypeMismatch { expected, found } => {
+                write!(f, "Type mismatch: expected {}, found {}", expected, found)
+            },
+            ParseError::KeyNotFound(key) => write!(f, "Key not found: \"{}\"", key),
+            ParseError::IndexOutOfBounds { index, len } => {
+                write!(f, "Index {} out of bounds for array of length {}", index, len)
+            },
+            ParseError::LengthMismatch { expected, found } => {
+                write!(f, "Length mismatch: expected an array of length {}, found {}", expected, found)
+            },
+            ParseError::OutOfRange { target } => write!(f, "Number out of range for {}", target),
+            ParseError::InvalidUtf8 { offset } => write!(f, "Invalid UTF-8 at byte offset {}", offset),
+            ParseError::UnexpectedToken { found, expected } => {
+                write!(f, "Unexpected Token: {}, expected {}", found, expected)
+            },
+            ParseError::UnexpectedEof { expected } => write!(f, "Unexpected EOF, expected {}", expected),
+            ParseError::UnexpectedChar(c) => write!(f, "Couldn't parse: {}", c),
+            ParseError::InvalidLiteral(literal) => write!(f, "Couldn't parse {}", literal),
+            ParseError::InvalidNumber(n) => write!(f, "Couldn't parse number: {}", n),
+            ParseError::UnterminatedString => write!(f, "Unexpected EOF, expected end of string `\"`"),
+            ParseError::UnterminatedComment => write!(f, "Unexpected EOF, expected end of comment `*/`"),
+            ParseError::InvalidEscape(escape) => write!(f, "Invalid escape sequence: {}", escape),
+            ParseError::MaxDepthExceeded(max) => write!(f, "Exceeded maximum nesting depth of {}", max),
+            ParseError::BudgetExceeded(max) => write!(f, "Exceeded token budget of {}", max),
+            ParseError::DuplicateKey(key) => write!(f, "Duplicate key: \"{}\"", key),
+            ParseError::InvalidSchema(reason) => write!(f, "Invalid schema: {}", reason),
+            ParseError::AtPath { error, path } => write!(f, "{} at \"{}\"", error, path),
+            ParseError::Located { error, offset, snippet, caret } => {
+                writeln!(f, "{} at offset {}", error, offset)?;
+                writeln!(f, "  {}", snippet)?;
+                write!(f, "  {}^", " ".repeat(*caret))
+            },
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Something that didn't stop the parse but changed the data, offset is the char offset
+// of the offending literal
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    // An integer literal too large for an integer Value, stored as a Float instead
+    IntegerPromoted {
+        literal: String,
+        offset: usize,
+    },
+    // A number literal with more precision than its Value holds, so it doesn't
+    // serialize back to the same digits
+    PrecisionLoss {
+        literal: String,
+        offset: usize,
+    },
+    // With ParserOptions::recover_missing_commas, a `,` was assumed before the token at offset
+    MissingComma {
+        offset: usize,
+    },
+    // In JSON5 mode, a number used as an object key was turned into the string key
+    NumericKey {
+        key: String,
+        offset: usize,
+    },
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::IntegerPromoted { literal, offset } => {
+                write!(f, "Integer {} at offset {} was stored as a float", literal, offset)
+            },
+            Warning::PrecisionLoss { literal, offset } => {
+                write!(f, "Number {} at offset {} lost precision", literal, offset)
+            },
+            Warning::MissingComma { offset } => write!(f, "Missing `,` before offset {}", offset),
+            Warning::NumericKey { key, offset } => {
+                write!(f, "Number key at offset {} was read as the string \"{}\"", offset, key)
+            },
+        }
+    }
+}
+
+// The significant digits of a decimal number, without sign, point, exponent and
+// leading or trailing zeros
+fn significant_digits(number: &str) -> String {
+    let mantissa = number.split(['e', 'E']).next().unwrap_or_default();
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    digits.trim_matches('0').to_string()
+}
+
+// Conversion out of a Value into a native Rust type
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, ParseError>;
+}
+
+fn mismatch<T>(expected: &'static str, found: &Value) -> Result<T, ParseError> {
+    Err(ParseError::TypeMismatch {
+        expected,
+        found: found.type_name(),
+    })
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        Ok(value)
+    }
+}
+
+fn integer_from_value<T: TryFrom<i128>>(value: Value, target: &'static str) -> Result<T, ParseError> {
+    let i = match value.into_unshared() {
+        Int(i) => i128::from(i),
+        BigInt(b) => b,
+        // Only if written as an integer
+        val @ Number { .. } => match val.raw_number().and_then(|raw| raw.parse().ok()) {
+            Some(i) => i,
+            None => return mismatch("integer", &val),
+        },
+        val => return mismatch("integer", &val),
+    };
+    T::try_from(i).map_err(|_| ParseError::OutOfRange { target })
+}
+
+impl FromValue for i32 {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        integer_from_value(value, "i32")
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        integer_from_value(value, "i64")
+    }
+}
+
+impl FromValue for u64 {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        integer_from_value(value, "u64")
+    }
+}
+
+impl FromValue for i128 {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        integer_from_value(value, "i128")
+    }
+}
+
+// Precision is lost silently, but a finite number beyond the f32 range is an error
+// rather than turning into an infinity
+impl FromValue for f32 {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        let fl = f64::from_value(value)?;
+        match fl as f32 {
+            narrowed if narrowed.is_infinite() && fl.is_finite() => Err(ParseError::OutOfRange { target: "f32" }),
+            narrowed => Ok(narrowed),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        match value.into_unshared() {
+            Float(fl) | Number { value: fl, .. } => Ok(fl),
+            Int(i) => Ok(i as f64),
+            BigInt(b) => Ok(b as f64),
+            val => mismatch("number", &val),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        match value.into_unshared() {
+            Bool(b) => Ok(b),
+            val => mismatch("bool", &val),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        match value.into_unshared() {
+            JsonString(s) => Ok(s),
+            val => mismatch("string", &val),
+        }
+    }
+}
+
+// null maps to None, anything else has to convert to T
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        match value.into_unshared() {
+            Null => Ok(None),
+            val => T::from_value(val).map(Some),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        match value.into_unshared() {
+            Array(v) => v.into_iter().map(T::from_value).collect(),
+            val => mismatch("array", &val),
+        }
+    }
+}
+
+impl<T: FromValue, const N: usize> FromValue for [T; N] {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        match value.into_unshared() {
+            Array(v) => fixed_array(v.len(), v.into_iter()),
+            val => mismatch("array", &val),
+        }
+    }
+}
+
+// Converts the len elements of an array into [T; N] as they are taken from elements, the
+// first failing element stops the rest from being taken
+fn fixed_array<T: FromValue, const N: usize>(
+    len: usize,
+    mut elements: impl Iterator<Item = Value>,
+) -> Result<[T; N], ParseError> {
+    if len != N {
+        return Err(ParseError::LengthMismatch { expected: N, found: len });
+    }
+
+    let mut error = None;
+    let arr: [Option<T>; N] = std::array::from_fn(|_| match error {
+        Some(_) => None,
+        None => T::from_value(elements.next().unwrap()).map_err(|e| error = Some(e)).ok(),
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(arr.map(Option::unwrap)),
+    }
+}
+
+impl<T: FromValue> FromValue for HashMap<String, T> {
+    fn from_value(value: Value) -> Result<Self, ParseError> {
+        match value.into_unshared() {
+            Object(map) => map.into_iter()
+                .map(|(k, v)| T::from_value(v).map(|v| (k, v)))
+                .collect(),
+            val => mismatch("object", &val),
+        }
+    }
+}
+
+// A minimal shape check: the type of each value and which object keys are required.
+// Keys not mentioned in an object schema are allowed and not checked
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Any,
+    Null,
+    Bool,
+    // Int or BigInt
+    Integer,
+    // Any number, integer or float
+    Number,
+    String,
+    // Every element must match
+    Array(Box<Schema>),
+    // Checked in key order, so the first mismatch reported is always the same one
+    Object(BTreeMap<String, FieldSchema>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+    pub schema: Schema,
+    pub required: bool,
+}
+
+impl Schema {
+    // Builds a schema from a declaration written as JSON: a type name ("any", "null",
+    // "bool", "integer", "number" or "string"), an array holding the element schema, or
+    // an object of field schemas. A key ending in `?` marks an optional field, e.g.
+    // `{"name": "string", "tags?": ["string"]}`
+    pub fn from_value(decl: &Value) -> Result<Schema, ParseError> {
+        match decl.resolve() {
+            JsonString(name) => match name.as_str() {
+                "any" => Ok(Schema::Any),
+                "null" => Ok(Schema::Null),
+                "bool" => Ok(Schema::Bool),
+                "integer" => Ok(Schema::Integer),
+                "number" => Ok(Schema::Number),
+                "string" => Ok(Schema::String),
+                _ => Err(ParseError::InvalidSchema(format!("unknown type \"{}\"", name))),
+            },
+            Array(v) => match v.as_slice() {
+                [] => Ok(Schema::Array(Box::new(Schema::Any))),
+                [element] => Ok(Schema::Array(Box::new(Schema::from_value(element)?))),
+                _ => Err(ParseError::InvalidSchema(String::from("an array declares a single element schema"))),
+            },
+            Object(map) => {
+                let mut fields = BTreeMap::new();
+                for (key, decl) in map {
+                    let (key, required) = match key.strip_suffix('?') {
+                        Some(key) => (key, false),
+                        None => (key.as_str(), true),
+                    };
+                    fields.insert(key.to_string(), FieldSchema {
+                        schema: Schema::from_value(decl)?,
+                        required,
+                    });
+                }
+                Ok(Schema::Object(fields))
+            },
+            val => Err(ParseError::InvalidSchema(format!("unexpected {} in declaration", val.type_name()))),
+        }
+    }
+
+    // The first mismatch is reported together with the path of the offending value
+    pub fn validate(&self, value: &Value) -> Result<(), ParseError> {
+        let mut path = Vec::new();
+        self.validate_at(value, &mut path).map_err(|error| ParseError::AtPath {
+            error: Box::new(error),
+            path: to_pointer(&path),
+        })
+    }
+
+    // On error, path is left pointing at the offending value
+    fn validate_at(&self, value: &Value, path: &mut Vec<String>) -> Result<(), ParseError> {
+        self.admits(value)?;
+        match (self, value.resolve()) {
+            (Schema::Array(element), Array(v)) => {
+                for (i, val) in v.iter().enumerate() {
+                    path.push(i.to_string());
+                    element.validate_at(val, path)?;
+                    path.pop();
+                }
+            },
+            (Schema::Object(fields), Object(map)) => {
+                for (key, field) in fields {
+                    path.push(key.clone());
+                    match map.get(key) {
+                        Some(val) => field.schema.validate_at(val, path)?,
+                        None if field.required => return Err(ParseError::KeyNotFound(key.clone())),
+                        None => {},
+                    }
+                    path.pop();
+                }
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    // Checks the type of value alone, not its elements or fields
+    fn admits(&self, value: &Value) -> Result<(), ParseError> {
+        match (self, value.resolve()) {
+            (Schema::Any, _)
+            | (Schema::Null, Null)
+            | (Schema::Bool, Bool(_))
+            | (Schema::Integer, Int(_) | BigInt(_))
+            | (Schema::Number, Int(_) | BigInt(_) | Float(_) | Number { .. })
+            | (Schema::String, JsonString(_))
+            | (Schema::Array(_), Array(_))
+            | (Schema::Object(_), Object(_)) => Ok(()),
+            (Schema::Integer, Number { raw, .. }) if raw.parse::<i128>().is_ok() => Ok(()),
+            (schema, val) => mismatch(schema.type_name(), val),
+        }
+    }
+
+    // The schema of the value at path relative to this one, None if it isn't checked
+    fn at(&self, path: &[String]) -> Option<&Schema> {
+        let (segment, rest) = match path.split_first() {
+            Some(split) => split,
+            None => return Some(self),
+        };
+        match self {
+            Schema::Array(element) => element.at(rest),
+            Schema::Object(fields) => fields.get(segment)?.schema.at(rest),
+            _ => None,
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Schema::Any => "any",
+            Schema::Null => "null",
+            Schema::Bool => "bool",
+            Schema::Integer => "integer",
+            Schema::Number => "number",
+            Schema::String => "string",
+            Schema::Array(_) => "array",
+            Schema::Object(_) => "object",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Token {
+    Value(Value), // Only primitive variants of Value are used in Token
+    CurlyBracketOpen,
+    CurlyBracketClose,
+    BracketOpen,
+    BracketClose,
+    Comma,
+    Colon,
+}
+
+// Tokenizes any source of chars, by default the chars of a &str
+pub struct Tokenizer<'a, I: Iterator<Item = char> = Chars<'a>> {
+    to_parse: Peekable<I>,
+    options: ParserOptions,
+    error: Option<ParseError>,
+    // Skipped comments are kept here when collecting them
+    collect_comments: bool,
+    comments: Vec<Comment>,
+    newline_seen: bool,
+    warnings: Vec<Warning>,
+    // Char offsets of the next char and of the start of the last scanned token
+    offset: usize,
+    token_start: usize,
+    // Line of the next char, counting from 1, and the char offset that line starts at
+    line: usize,
+    line_start: usize,
+    // Tokens scanned so far, checked against options.max_tokens
+    tokens: usize,
+    // Where the next string is read into, see recycle
+    string_buf: String,
+    // Where number literals are gathered, kept between numbers for its allocation
+    number_buf: String,
+    marker: PhantomData<&'a str>,
+}
+
+// A comment including its delimiters, own_line is set if only whitespace precedes
+// it on its line
+struct Comment {
+    text: String,
+    own_line: bool,
+    position: Position,
+}
+
+// A place in the input: the char offset, and the line and column it is on, both counting
+// from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    pub fn new(to_parse: &'a str) -> Tokenizer<'a> {
+        Tokenizer::with_options(to_parse, &ParserOptions::default())
+    }
+
+    pub fn with_options(to_parse: &'a str, options: &ParserOptions) -> Tokenizer<'a> {
+        Tokenizer::from_chars_with_options(to_parse.chars(), options)
+    }
+}
+
+impl<'a, I: Iterator<Item = char>> Tokenizer<'a, I> {
+    pub fn from_chars_with_options(chars: I, options: &ParserOptions) -> Tokenizer<'a, I> {
+        Tokenizer {
+            to_parse: chars.peekable(),
+            options: options.clone(),
+            error: None,
+            collect_comments: false,
+            comments: Vec::new(),
+            newline_seen: false,
+            warnings: Vec::new(),
+            offset: 0,
+            token_start: 0,
+            line: 1,
+            line_start: 0,
+            tokens: 0,
+            string_buf: String::new(),
+            number_buf: String::new(),
+            marker: PhantomData,
+        }
+    }
+
+    // The reason the token stream ended early, if it did
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    // Lossy number conversions so far
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    // Number of chars consumed so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    // Hands back the String of a string token that was copied rather than kept, to read
+    // the next string into its allocation
+    fn recycle(&mut self, mut s: String) {
+        s.clear();
+        self.string_buf = s;
+    }
+
+    pub fn next_token(&mut self) -> Option<Token> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        match self.scan_token() {
+            Ok(Some(tok)) => {
+                self.tokens += 1;
+                match self.options.max_tokens {
+                    Some(max) if self.tokens > max => {
+                        self.error = Some(ParseError::BudgetExceeded(max));
+                        None
+                    },
+                    _ => Some(tok),
+                }
+            },
+            Ok(None) => None,
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        }
+    }
+
+    fn scan_token(&mut self) -> Result<Option<Token>, ParseError> {
+        loop {
+            self.token_start = self.offset;
+            let c = match self.to_parse.peek() {
+                Some(c) => *c,
+                None => return Ok(None),
+            };
+
+            let tok = match c {
+                '{' => self.single(Token::CurlyBracketOpen),
+                '}' => self.single(Token::CurlyBracketClose),
+                '[' => self.single(Token::BracketOpen),
+                ']' => self.single(Token::BracketClose),
+                ',' => self.single(Token::Comma),
+                ':' => self.single(Token::Colon),
+                '"' => self.next_string()?,
+                c if c.is_whitespace() => {
+                    if c == '\n' {
+                        self.newline_seen = true;
+                    }
+                    self.advance();
+                    continue;
+                },
+                '/' if self.options.allow_comments => {
+                    self.skip_comment()?;
+                    continue;
+                },
+                't' => self.next_literal("true", Bool(true))?,
+                'f' => self.next_literal("false", Bool(false))?,
+                'n' => self.next_literal("null", Null)?,
+                '-' | '0'..='9' => self.next_number()?,
+                '+' if self.options.json5 => self.next_number()?,
+                c if self.options.extra_whitespace.is_some_and(|skip| skip(c)) => {
+                    self.advance();
+                    continue;
+                },
+                c => return Err(ParseError::UnexpectedChar(c)),
+            };
+
+            self.newline_seen = false;
+            return Ok(Some(tok));
+        }
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.to_parse.next();
+        if let Some(c) = c {
+            self.offset += 1;
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.offset;
+            }
+        }
+        c
+    }
+
+    // Where the next char is
+    pub fn current_position(&self) -> Position {
+        Position {
+            offset: self.offset,
+            line: self.line,
+            column: self.offset - self.line_start + 1,
+        }
+    }
+
+    fn single(&mut self, tok: Token) -> Token {
+        self.advance();
+        tok
+    }
+
+    // Skips a // line comment or a /* */ block comment
+    fn skip_comment(&mut self) -> Result<(), ParseError> {
+        let position = self.current_position();
+        // consume /
+        self.advance();
+
+        let mut text = String::from("/");
+        let ends_line = match self.advance() {
+            Some('/') => {
+                text.push('/');
+                while let Some(c) = self.advance() {
+                    if c == '\n' {
+                        break;
+                    }
+                    text.push(c);
+                }
+                true
+            },
+            Some('*') => {
+                text.push('*');
+                let mut closed = false;
+                while let Some(c) = self.advance() {
+                    text.push(c);
+                    if text.len() > 3 && text.ends_with("*/") {
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed {
+                    return Err(ParseError::UnterminatedComment);
+                }
+                false
+            },
+            _ => return Err(ParseError::UnexpectedChar('/')),
+        };
+
+        if self.collect_comments {
+            self.comments.push(Comment {
+                text: text.trim_end().to_string(),
+                own_line: self.newline_seen,
+                position,
+            });
+        }
+        self.newline_seen = ends_line;
+        Ok(())
+    }
+
+    fn next_number(&mut self) -> Result<Token, ParseError> {
+        fn is_number_char(c: char) -> bool {
+            c.is_ascii_digit() || ['.', '-', '+', 'e', 'E'].contains(&c)
+        }
+
+        let json5 = self.options.json5;
+        let is_hex_prefix = |c: char| json5 && (c == 'x' || c == 'X');
+        let underscores = self.options.allow_number_underscores;
+        let is_number_char = |c: char| is_number_char(c) || (underscores && c == '_');
+        let mut found_number = std::mem::take(&mut self.number_buf);
+        found_number.clear();
+
+        if let Some(first) = self.advance() {
+            // Single char numbers like 0 and 1 are common enough to skip building a String
+            if !self.to_parse.peek().is_some_and(|&c| is_number_char(c) || c.is_ascii_alphanumeric()) {
+                // A lone digit is always valid and fits any integer type, only a hook or
+                // raw_numbers still needs to see it as a literal
+                if let Some(digit) = first.to_digit(10).filter(|_| self.options.number_hook.is_none() && !self.options.raw_numbers) {
+                    return Ok(Token::Value(Int(digit.into())));
+                }
+                let mut buf = [0; 4];
+                return self.number_token(first.encode_utf8(&mut buf));
+            }
+            found_number.push(first);
+        }
+
+        let mut hex = false;
+        while let Some(&c) = self.to_parse.peek() {
+            let accepted = if hex {
+                // Take any letter so that a bad digit fails the whole literal
+                c.is_ascii_alphanumeric() || (underscores && c == '_')
+            } else if is_hex_prefix(c) && ["0", "-0", "+0"].contains(&found_number.as_str()) {
+                hex = true;
+                true
+            } else {
+                is_number_char(c)
+            };
+            if !accepted {
+                break;
+            }
+            found_number.push(c);
+            self.advance();
+        }
+
+        // Letters right after a number, such as the x of a hex literal outside of JSON5,
+        // make the whole literal invalid instead of being left for the next token
+        if self.to_parse.peek().is_some_and(|c| c.is_ascii_alphanumeric()) {
+            while let Some(&c) = self.to_parse.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                found_number.push(c);
+                self.advance();
+            }
+            return Err(ParseError::InvalidNumber(found_number));
+        }
+
+        // A JSON5 `+` is dropped, so that the literal stays valid JSON for raw_numbers
+        if let Some(unsigned) = found_number.strip_prefix('+') {
+            if !unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+                return Err(ParseError::InvalidNumber(found_number));
+            }
+            found_number = unsigned.to_string();
+        }
+
+        if found_number.contains('_') {
+            found_number = strip_underscores(&found_number, hex)
+                .ok_or(ParseError::InvalidNumber(found_number))?;
+        }
+
+        if hex {
+            return self.hex_token(found_number);
+        }
+        let tok = self.number_token(&found_number);
+        self.number_buf = found_number;
+        tok
+    }
+
+    // A JSON5 hex integer such as 0xFF or -0x1f, too large for i64 it needs big_integers
+    fn hex_token(&self, literal: String) -> Result<Token, ParseError> {
+        let (negative, digits) = match literal.strip_prefix('-') {
+            Some(rest) => (true, &rest[2..]),
+            None => (false, &literal[2..]),
+        };
+
+        let parsed = i128::from_str_radix(digits, 16)
+            .ok()
+            .map(|i| if negative { -i } else { i });
+        match parsed {
+            Some(i) if i64::try_from(i).is_ok() => Ok(Token::Value(Int(i as i64))),
+            Some(i) if self.options.big_integers => Ok(Token::Value(BigInt(i))),
+            _ => Err(ParseError::InvalidNumber(literal)),
+        }
+    }
+
+    fn number_token(&mut self, literal: &str) -> Result<Token, ParseError> {
+        if !is_number_literal(literal, self.options.json5) {
+            return Err(ParseError::InvalidNumber(literal.to_string()));
+        }
+        if let Some(hook) = self.options.number_hook {
+            return hook(literal, &self.options).map(Token::Value);
+        }
+
+        let val = default_number_hook(literal, &self.options)?;
+
+        // Nothing is lost with the literal kept, so there is nothing to warn about
+        if self.options.raw_numbers {
+            let value = val.as_f64().unwrap_or(f64::NAN);
+            return Ok(Token::Value(Number { value, raw: literal.to_string() }));
+        }
+
+        if let Float(fl) = val {
+            let offset = self.token_start;
+            if !literal.contains(['.', 'e', 'E']) {
+                self.warnings.push(Warning::IntegerPromoted { literal: literal.to_string(), offset });
+            }
+            // `{:e}` gives the shortest digits that read back as fl
+            if !fl.is_finite() || significant_digits(&format!("{:e}", fl)) != significant_digits(literal) {
+                self.warnings.push(Warning::PrecisionLoss { literal: literal.to_string(), offset });
+            }
+        }
+
+        Ok(Token::Value(val))
+    }
+
+    // Consumes exactly `literal`, we know its first char is next
+    fn next_literal(&mut self, literal: &'static str, value: Value) -> Result<Token, ParseError> {
+        for c in literal.chars() {
+            match self.advance() {
+                Some(parsed_c) if parsed_c == c => {},
+                Some(_) => return Err(ParseError::InvalidLiteral(literal)),
+                None => return Err(ParseError::UnexpectedEof {
+                    expected: format!("`{}`", literal),
+                }),
+            }
+        }
+
+        Ok(Token::Value(value))
+    }
+
+    fn next_string(&mut self) -> Result<Token, ParseError> {
+        // consume "
+        self.advance();
+
+        // The raw contents, escape sequences are decoded once the string is complete
+        let mut found_str = std::mem::take(&mut self.string_buf);
+        let mut has_escapes = false;
+        let mut is_escaped = false;
+        while let Some(c) = self.advance() {
+            if is_escaped {
+                is_escaped = false;
+            } else if c == '\\' {
+                has_escapes = true;
+                is_escaped = true;
+            } else if c == '"' {
+                if has_escapes {
+                    found_str = unescape_json_string(&found_str)?;
+                }
+                return Ok(Token::Value(JsonString(found_str)));
+            }
+            found_str.push(c);
+        }
+
+        Err(ParseError::UnterminatedString)
+    }
+}
+
+// Whether literal follows the JSON number syntax: no leading zeros, and digits on both
+// sides of a `.` and after an exponent. JSON5 also allows a trailing `.`, as in `5.`
+fn is_number_literal(literal: &str, json5: bool) -> bool {
+    fn digits(s: &str) -> (&str, &str) {
+        s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+    }
+
+    let (int, rest) = digits(literal.strip_prefix('-').unwrap_or(literal));
+    if int.is_empty() || (int.len() > 1 && int.starts_with('0')) {
+        return false;
+    }
+    let rest = match rest.strip_prefix('.') {
+        Some(fraction) => {
+            let (fraction, rest) = digits(fraction);
+            if fraction.is_empty() && !json5 {
+                return false;
+            }
+            rest
+        },
+        None => rest,
+    };
+    match rest.strip_prefix(['e', 'E']) {
+        Some(exponent) => {
+            let (exponent, rest) = digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent));
+            !exponent.is_empty() && rest.is_empty()
+        },
+        None => rest.is_empty(),
+    }
+}
+
+// The literal without its `_` separators, None if one of them isn't between two digits
+fn strip_underscores(literal: &str, hex: bool) -> Option<String> {
+    let is_digit = |c: Option<&char>| c.is_some_and(|c| if hex { c.is_ascii_hexdigit() } else { c.is_ascii_digit() });
+    let chars: Vec<char> = literal.chars().collect();
+    for (i, c) in chars.iter().enumerate() {
+        if *c == '_' && !(i > 0 && is_digit(chars.get(i - 1)) && is_digit(chars.get(i + 1))) {
+            return None;
+        }
+    }
+    Some(literal.replace('_', ""))
+}
+
+// Decodes the escape sequences in the contents of a JSON string literal, given without
+// its quotes: \" \\ \/ \b \f \n \r \t and \uXXXX, including surrogate pairs
+pub fn unescape_json_string(s: &str) -> Result<String, ParseError> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        let decoded = match chars.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('/') => '/',
+            Some('b') => '\u{08}',
+            Some('f') => '\u{0C}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => unescape_unicode(&mut chars)?,
+            Some(other) => return Err(ParseError::InvalidEscape(format!("\\{}", other))),
+            None => return Err(ParseError::InvalidEscape(String::from("\\"))),
+        };
+        unescaped.push(decoded);
+    }
+    Ok(unescaped)
+}
+
+// The char of a \uXXXX escape whose `\u` was just consumed, a high surrogate has to be
+// followed by an escaped low surrogate
+fn unescape_unicode(chars: &mut Chars<'_>) -> Result<char, ParseError> {
+    fn hex4(chars: &mut Chars<'_>) -> Result<u32, ParseError> {
+        let digits: String = chars.take(4).collect();
+        if digits.len() == 4 && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            u32::from_str_radix(&digits, 16).map_err(|_| ParseError::InvalidEscape(format!("\\u{}", digits)))
+        } else {
+            Err(ParseError::InvalidEscape(format!("\\u{}", digits)))
+        }
+    }
+
+    let high = hex4(chars)?;
+    let code = match high {
+        0xD800..=0xDBFF => {
+            if chars.next() != Some('\\') || chars.next() != Some('u') {
+                return Err(ParseError::InvalidEscape(format!("\\u{:04X} without a low surrogate", high)));
+            }
+            let low = hex4(chars)?;
+            if !(0xDC00..=0xDFFF).contains(&low) {
+                return Err(ParseError::InvalidEscape(format!("\\u{:04X}\\u{:04X}", high, low)));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        },
+        code => code,
+    };
+
+    // Only a lone low surrogate is left that isn't a char
+    char::from_u32(code).ok_or_else(|| ParseError::InvalidEscape(format!("\\u{:04X}", code)))
+}
+
+impl<I: Iterator<Item = char>> Iterator for Tokenizer<'_, I> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
+
+// Parses UTF-8 bytes in a single pass, decoding chars as the tokenizer asks for them.
+// Like FromStr, only whitespace may follow the value
+pub fn from_slice(bytes: &[u8]) -> Result<Value, ParseError> {
+    let mut chars = Utf8Chars {
+        bytes,
+        offset: 0,
+        invalid_at: None,
+    };
+
+    let mut parser: Parser<_> = Parser::from_chars_with_options(chars.by_ref(), ParserOptions::default());
+    let parsed = parser.parse_document();
+    // Decoding stops at the first invalid byte, whatever the parser made of that is secondary
+    match chars.invalid_at {
+        Some(offset) => Err(ParseError::InvalidUtf8 { offset }),
+        None => parsed,
+    }
+}
+
+// Pretty-prints a JSON document with indent spaces per level. Numbers are written exactly
+// as in input, but the order of object entries isn't kept
+pub fn reformat(input: &str, indent: usize) -> Result<String, ParseError> {
+    let val = ParserBuilder::new().raw_numbers(true).build(input).parse_document()?;
+    // Without any Float there is nothing that could fail to serialize
+    Ok(val.to_string_with(&SerializeOptions::new().pretty(true).indent(indent)))
+}
+
+// The document without any whitespace outside of strings, numbers kept as written
+pub fn minify(input: &str) -> Result<String, ParseError> {
+    let val = ParserBuilder::new().raw_numbers(true).build(input).parse_document()?;
+    Ok(val.to_string_compact())
+}
+
+// Reads a config file of `key = value` lines into an Object. The key is everything before
+// the first `=`, trimmed, and the value the rest of the line as a JSON value, which may
+// be followed by a // comment. Blank lines and lines starting with # are skipped, and
+// of a repeated key the last value wins
+pub fn parse_config(input: &str) -> Result<Value, ParseError> {
+    let mut map = HashMap::new();
+    // Char offset of the current line, for locating errors within input
+    let mut line_start = 0;
+
+    for line in input.split('\n') {
+        let offset = line_start;
+        line_start += line.chars().count() + 1;
+
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some(pair) => pair,
+            None => {
+                let err = ParseError::UnexpectedEof { expected: String::from("`=`") };
+                return Err(err.located(input, offset + line.trim_end().chars().count()));
+            },
+        };
+        let value_offset = offset + key.chars().count() + 1;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(ParseError::UnexpectedChar('=').located(input, value_offset - 1));
+        }
+
+        let val = ParserBuilder::new()
+            .allow_comments(true)
+            .build(value)
+            .parse_document()
+            .map_err(|e| match e {
+                ParseError::Located { error, offset, .. } => error.located(input, value_offset + offset),
+                e => e.located(input, value_offset),
+            })?;
+        map.insert(key.to_string(), val);
+    }
+
+    Ok(Object(map))
+}
+
+// Decodes chars from bytes, ending early at the first invalid sequence
+struct Utf8Chars<'b> {
+    bytes: &'b [u8],
+    offset: usize,
+    invalid_at: Option<usize>,
+}
+
+impl Iterator for Utf8Chars<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.invalid_at.is_some() {
+            return None;
+        }
+        let first = *self.bytes.get(self.offset)?;
+
+        let len = match first {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 0,
+        };
+
+        let decoded = self.bytes.get(self.offset..self.offset + len)
+            .filter(|_| len > 0)
+            .and_then(|seq| std::str::from_utf8(seq).ok())
+            .and_then(|s| s.chars().next());
+
+        match decoded {
+            Some(c) => {
+                self.offset += len;
+                Some(c)
+            },
+            None => {
+                self.invalid_at = Some(self.offset);
+                None
+            }
+        }
+    }
+}
+
+// The shape of a value, such as the top level of a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Object,
+    Array,
+    String,
+    Number,
+    Bool,
+    Null,
+}
+
+// Looks only at the first significant token, so it doesn't validate the rest of the input
+pub fn peek_kind(input: &str) -> Option<Kind> {
+    match Tokenizer::new(input).next_token()? {
+        Token::CurlyBracketOpen => Some(Kind::Object),
+        Token::BracketOpen => Some(Kind::Array),
+        Token::Value(val) => Some(val.kind()),
+        _ => None,
+    }
+}
+
+// What building the tree of a document takes, as counted by estimate_size. Object keys
+// count towards strings and string_bytes, they are allocated as strings too
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeEstimate {
+    // Every value, containers and scalars alike
+    pub values: usize,
+    pub objects: usize,
+    pub arrays: usize,
+    pub strings: usize,
+    // UTF-8 bytes of all strings once unescaped
+    pub string_bytes: usize,
+}
+
+// Parser::estimate_size with the default options
+pub fn estimate_size(input: &str) -> Result<SizeEstimate, ParseError> {
+    Parser::new(input).estimate_size()
+}
+
+// Turns a number literal into a Value, or rejects it, see ParserOptions::number_hook
+pub type NumberHook = fn(&str, &ParserOptions) -> Result<Value, ParseError>;
+
+// What the parser makes of a number literal under options without a number_hook, for a
+// hook to fall back to: an i64 if it fits, then an i128 (covering all of u64) with
+// big_integers, and a float otherwise
+pub fn default_number_hook(literal: &str, options: &ParserOptions) -> Result<Value, ParseError> {
+    if let Ok(i) = literal.parse::<i64>() {
+        Ok(Int(i))
+    } else if let Some(b) = literal.parse::<i128>().ok().filter(|_| options.big_integers) {
+        Ok(BigInt(b))
+    } else {
+        literal.parse::<f64>().map(Float).map_err(|_| ParseError::InvalidNumber(literal.to_string()))
+    }
+}
+
+// A Tokenizer with one token of lookahead, that still gives access to the tokenizer's error
+struct TokenStream<'a, I: Iterator<Item = char> = Chars<'a>> {
+    t: Tokenizer<'a, I>,
+    peeked: Option<Token>,
+    // Char offsets where the peeked token and the last token returned by next start
+    peeked_start: usize,
+    start: usize,
+}
+
+impl<'a, I: Iterator<Item = char>> TokenStream<'a, I> {
+    fn new(t: Tokenizer<'a, I>) -> TokenStream<'a, I> {
+        TokenStream {
+            t,
+            peeked: None,
+            peeked_start: 0,
+            start: 0,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        if self.peeked.is_none() {
+            self.peeked = self.t.next_token();
+            self.peeked_start = self.t.token_start;
+        }
+        self.peeked.as_ref()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        match self.peeked.take() {
+            Some(tok) => {
+                self.start = self.peeked_start;
+                Some(tok)
+            },
+            None => {
+                let tok = self.t.next_token();
+                self.start = self.t.token_start;
+                tok
+            },
+        }
+    }
+
+    // Puts back the token last returned by next
+    fn unread(&mut self, tok: Token) {
+        self.peeked = Some(tok);
+        self.peeked_start = self.start;
+    }
+
+    // Comments between the last consumed token and the next one
+    fn take_comments(&mut self) -> Vec<Comment> {
+        self.peek();
+        std::mem::take(&mut self.t.comments)
+    }
+
+    // Error for finding `found` where one of `expected` would have been valid. If the
+    // stream ended because the tokenizer failed, that failure is the more useful error
+    fn unexpected(&self, found: Option<&Token>, expected: &[&str]) -> ParseError {
+        if found.is_none() {
+            if let Some(e) = self.t.error() {
+                return e.clone();
+            }
+        }
+        unexpected(found, expected)
+    }
+}
+
+// What to do when an object contains the same key more than once
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DuplicateKeys {
+    TakeFirst,
+    #[default]
+    TakeLast,
+    Error,
+    // All values of a repeated key are gathered into an Array, in input order. A key that
+    // occurs once keeps its plain value, even if that is an array itself. Arrays are never
+    // merged: {"a": [1], "a": 2} collects into [[1], 2]
+    Collect,
+}
+
+// Nesting limit when ParserOptions::max_depth is None. Arrays and objects are parsed
+// recursively, so without a limit deeply nested input would overflow the stack
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptions {
+    // Skip // line and /* block */ comments
+    pub allow_comments: bool,
+    // Accept a single `,` before a closing `]` or `}`
+    pub allow_trailing_commas: bool,
+    // Maximum nesting of arrays and objects, None for DEFAULT_MAX_DEPTH
+    pub max_depth: Option<usize>,
+    pub duplicate_keys: DuplicateKeys,
+    // Integers that overflow i64 but fit into i128 (e.g. any u64) become BigInt
+    // instead of an imprecise Float. Precedence is i64, then i128, then f64
+    pub big_integers: bool,
+    // Accept the JSON5 extensions: hexadecimal integers like 0xFF and a leading `+`. A
+    // number in key position becomes a string key, see Warning::NumericKey
+    pub json5: bool,
+    // Parse every decimal number into a Number that keeps its literal, for echoing
+    // numbers back exactly as they were received
+    pub raw_numbers: bool,
+    // Where a `,` is missing between array elements or object entries, carry on as if it
+    // were there and record a Warning::MissingComma
+    pub recover_missing_commas: bool,
+    // Accept `_` between the digits of a number, as in 1_000_000
+    pub allow_number_underscores: bool,
+    // Reject a scalar as the whole document, as RFC 4627 did, rather than allow any value
+    pub require_compound_root: bool,
+    // Chars to skip between tokens like whitespace, e.g. |c| c == ';' for JSON embedded in
+    // another format. Only asked about chars that can't begin a token
+    pub extra_whitespace: Option<fn(char) -> bool>,
+    // Give up with BudgetExceeded once the input needs more than this many tokens, which
+    // bounds the work spent on untrusted input regardless of its size. None for no limit
+    pub max_tokens: Option<usize>,
+    // Called with every decimal number literal instead of turning it into an Int or Float,
+    // e.g. for an arbitrary precision decimal type, or to reject numbers beyond some range
+    // with an error that fails the parse. The literal is only checked to follow the JSON
+    // number syntax, its size or precision is the hook's business. The hook gets these
+    // options to fall back to default_number_hook with. Takes precedence over
+    // raw_numbers, JSON5 hex integers don't go through it
+    pub number_hook: Option<NumberHook>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParserBuilder {
+    options: ParserOptions,
+}
+
+impl ParserBuilder {
+    pub fn new() -> ParserBuilder {
+        ParserBuilder::default()
+    }
+
+    pub fn allow_comments(mut self, allow: bool) -> ParserBuilder {
+        self.options.allow_comments = allow;
+        self
+    }
+
+    pub fn allow_trailing_commas(mut self, allow: bool) -> ParserBuilder {
+        self.options.allow_trailing_commas = allow;
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> ParserBuilder {
+        self.options.max_depth = Some(depth);
+        self
+    }
+
+    pub fn duplicate_keys(mut self, policy: DuplicateKeys) -> ParserBuilder {
+        self.options.duplicate_keys = policy;
+        self
+    }
+
+    pub fn big_integers(mut self, enable: bool) -> ParserBuilder {
+        self.options.big_integers = enable;
+        self
+    }
+
+    pub fn json5(mut self, enable: bool) -> ParserBuilder {
+        self.options.json5 = enable;
+        self
+    }
+
+    pub fn raw_numbers(mut self, enable: bool) -> ParserBuilder {
+        self.options.raw_numbers = enable;
+        self
+    }
+
+    pub fn recover_missing_commas(mut self, enable: bool) -> ParserBuilder {
+        self.options.recover_missing_commas = enable;
+        self
+    }
+
+    pub fn allow_number_underscores(mut self, allow: bool) -> ParserBuilder {
+        self.options.allow_number_underscores = allow;
+        self
+    }
+
+    pub fn require_compound_root(mut self, require: bool) -> ParserBuilder {
+        self.options.require_compound_root = require;
+        self
+    }
+
+    pub fn extra_whitespace(mut self, skip: fn(char) -> bool) -> ParserBuilder {
+        self.options.extra_whitespace = Some(skip);
+        self
+    }
+
+    pub fn max_tokens(mut self, max: usize) -> ParserBuilder {
+        self.options.max_tokens = Some(max);
+        self
+    }
+
+    pub fn number_hook(mut self, hook: NumberHook) -> ParserBuilder {
+        self.options.number_hook = Some(hook);
+        self
+    }
+
+    pub fn build(self, input: &str) -> Parser<'_> {
+        Parser::with_options(input, self.options)
+    }
+
+    #[cfg(feature = "arena")]
+    pub fn build_arena<'a, 'b>(self, input: &'a str, bump: &'b Bump) -> ArenaParser<'a, 'b> {
+        ArenaParser::with_options(input, self.options, bump)
+    }
+
+    pub fn parse(self, input: &str) -> Result<Value, ParseError> {
+        self.build(input).try_parse()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValueComments {
+    // Comments on the lines before the value
+    pub leading: Vec<String>,
+    // Comments after the value on the same line
+    pub trailing: Vec<String>,
+}
+
+// Comments of a document, keyed by the JSON Pointer of the value they belong to ("" is the root)
+pub type Comments = HashMap<String, ValueComments>;
+
+// Escapes path segments into a JSON Pointer
+fn to_pointer(segments: &[String]) -> String {
+    segments.iter()
+        .map(|seg| format!("/{}", seg.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+pub struct Parser<'a, I: Iterator<Item = char> = Chars<'a>> {
+    t: TokenStream<'a, I>,
+    options: ParserOptions,
+    depth: usize,
+    // Only used by parse_with_comments
+    comments: Option<Comments>,
+    path: Vec<String>,
+    last_value: Option<String>,
+    // The whole input, if the parser was made from a &str, to quote it in errors
+    input: Option<&'a str>,
+    // Only used by parse_validated
+    schema: Option<Schema>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a str) -> Parser<'a> {
+        Parser::with_options(input, ParserOptions::default())
+    }
+
+    // Parsing raw bytes, malformed UTF-8 is reported with its offset instead of panicking
+    pub fn from_bytes(input: &'a [u8]) -> Result<Parser<'a>, ParseError> {
+        let input = std::str::from_utf8(input)
+            .map_err(|e| ParseError::InvalidUtf8 { offset: e.valid_up_to() })?;
+        Ok(Parser::new(input))
+    }
+
+    pub fn with_options(input: &'a str, options: ParserOptions) -> Parser<'a> {
+        let mut parser = Parser::from_chars_with_options(input.chars(), options);
+        parser.input = Some(input);
+        parser
+    }
+}
+
+impl<'a, I: Iterator<Item = char>> Parser<'a, I> {
+    // Parsing chars from any source, e.g. a decoder, without collecting them into a String
+    // first. With options, see Tokenizer::from_chars_with_options and from_tokenizer
+    pub fn from_chars(chars: I) -> Parser<'a, I> {
+        Parser::from_chars_with_options(chars, ParserOptions::default())
+    }
+}
+
+impl<'a, I: Iterator<Item = char>> Parser<'a, I> {
+    fn from_chars_with_options(chars: I, options: ParserOptions) -> Parser<'a, I> {
+        Parser::from_tokenizer(Tokenizer::from_chars_with_options(chars, &options))
+    }
+
+    // Drives the parser with an already set up tokenizer, whose options the parser
+    // adopts. The input can be preprocessed through the tokenizer's char iterator
+    pub fn from_tokenizer(t: Tokenizer<'a, I>) -> Parser<'a, I> {
+        Parser {
+            options: t.options.clone(),
+            t: TokenStream::new(t),
+            depth: 0,
+            comments: None,
+            path: Vec::new(),
+            last_value: None,
+            input: None,
+            schema: None,
+        }
+    }
+
+    // Prints the error and returns None if the input is not valid
+    pub fn parse(self) -> Option<Value> {
+        match self.try_parse() {
+            Ok(val) => Some(val),
+            Err(e) => {
+                println!("{}", e);
+                None
+            }
+        }
+    }
+
+    // Errors carry a snippet of the input around the failure if the parser was made
+    // from a &str
+    pub fn try_parse(mut self) -> Result<Value, ParseError> {
+        self.parse_root().map_err(|e| self.locate(e))
+    }
+
+    // Parses a single value and hands back the parser positioned right after it,
+    // whatever follows is left for the caller to deal with
+    pub fn parse_one(mut self) -> Result<(Value, Parser<'a, I>), ParseError> {
+        match self.parse_root() {
+            Ok(val) => Ok((val, self)),
+            Err(e) => Err(self.locate(e)),
+        }
+    }
+
+    // Folds over the elements of a top-level array as they are parsed, each element is
+    // dropped after f has seen it so memory stays bounded by the largest element
+    pub fn fold_array<T>(mut self, init: T, mut f: impl FnMut(T, Value) -> T) -> Result<T, ParseError> {
+        self.fold_elements(init, &mut f).map_err(|e| self.locate(e))
+    }
+
+    fn fold_elements<T>(&mut self, init: T, f: &mut impl FnMut(T, Value) -> T) -> Result<T, ParseError> {
+        match self.t.next() {
+            Some(Token::BracketOpen) => {},
+            tok => return Err(self.t.unexpected(tok.as_ref(), &["`[`"])),
+        }
+
+        let mut acc = init;
+        if let Some(Token::BracketClose) = self.t.peek() {
+            self.t.next();
+            return Ok(acc);
+        }
+
+        // The top-level array counts towards max_depth like any other
+        self.depth += 1;
+        let max = self.options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        if self.depth > max {
+            return Err(ParseError::MaxDepthExceeded(max));
+        }
+
+        loop {
+            acc = f(acc, self.parse_value()?);
+
+            // Consuming , or ]
+            match self.t.next() {
+                Some(Token::Comma) => {
+                    if self.trailing_comma_closes(|tok| matches!(tok, Token::BracketClose)) {
+                        return Ok(acc);
+                    }
+                },
+                Some(Token::BracketClose) => return Ok(acc),
+                Some(tok) if self.options.recover_missing_commas && starts_value(&tok) => self.missing_comma(tok),
+                tok => return Err(self.t.unexpected(tok.as_ref(), &["`,`", "`]`"])),
+            }
+        }
+    }
+
+    // Char offsets where each element of a top-level array starts and where the `,` or `]`
+    // following it starts
+    fn scan_elements(&mut self) -> Result<Vec<(usize, usize)>, ParseError> {
+        match self.t.next() {
+            Some(Token::BracketOpen) => {},
+            tok => return Err(self.t.unexpected(tok.as_ref(), &["`[`"])),
+        }
+
+        let mut spans = Vec::new();
+        if let Some(Token::BracketClose) = self.t.peek() {
+            self.t.next();
+        } else {
+            loop {
+                let tok = self.t.next();
+                let start = self.t.start;
+                self.skip_value(tok)?;
+
+                // Consuming , or ]
+                match self.t.next() {
+                    Some(Token::Comma) => {
+                        spans.push((start, self.t.start));
+                        if self.trailing_comma_closes(|tok| matches!(tok, Token::BracketClose)) {
+                            break;
+                        }
+                    },
+                    Some(Token::BracketClose) => {
+                        spans.push((start, self.t.start));
+                        break;
+                    },
+                    tok => return Err(self.t.unexpected(tok.as_ref(), &["`,`", "`]`"])),
+                }
+            }
+        }
+
+        match self.t.next() {
+            None if self.t.t.error().is_none() => Ok(spans),
+            tok => Err(self.t.unexpected(tok.as_ref(), &["end of input"])),
+        }
+    }
+
+    // Consumes the tokens of the value starting with tok, which is an element of the
+    // top-level array. Only checks that brackets match up and nest no deeper than allowed
+    fn skip_value(&mut self, mut tok: Option<Token>) -> Result<(), ParseError> {
+        let max = self.options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        // Whether each open bracket is a `[`, innermost last
+        let mut open: Vec<bool> = Vec::new();
+        loop {
+            let expected: &[&str] = match open.last() {
+                None => &["a value"],
+                Some(true) => &["`]`"],
+                Some(false) => &["`}`"],
+            };
+            match tok {
+                Some(Token::BracketOpen) => open.push(true),
+                Some(Token::CurlyBracketOpen) => open.push(false),
+                Some(Token::BracketClose) if open.last() == Some(&true) => {
+                    open.pop();
+                },
+                Some(Token::CurlyBracketClose) if open.last() == Some(&false) => {
+                    open.pop();
+                },
+                Some(Token::Value(_)) => {},
+                Some(Token::Comma) | Some(Token::Colon) if !open.is_empty() => {},
+                tok => return Err(self.t.unexpected(tok.as_ref(), expected)),
+            }
+
+            // The top-level array is one level already
+            if open.len() + 1 > max {
+                return Err(ParseError::MaxDepthExceeded(max));
+            }
+            if open.is_empty() {
+                return Ok(());
+            }
+            tok = self.t.next();
+        }
+    }
+
+    // Parses and checks against schema in the same pass, each value as soon as it is read,
+    // see Schema::validate. The first mismatch fails the parse with the path of the
+    // offending value, located at where that value starts in the input
+    pub fn parse_validated(mut self, schema: &Schema) -> Result<Value, ParseError> {
+        self.schema = Some(schema.clone());
+        self.try_parse()
+    }
+
+    fn parse_root(&mut self) -> Result<Value, ParseError> {
+        let mut tree = Tree { duplicate_keys: self.options.duplicate_keys };
+        self.build_root(&mut tree)
+    }
+
+    // The top-level value, which with require_compound_root must be an object or array
+    fn build_root<B: Build>(&mut self, b: &mut B) -> Result<B::Value, ParseError> {
+        if self.options.require_compound_root
+            && !matches!(self.t.peek(), Some(Token::CurlyBracketOpen | Token::BracketOpen))
+        {
+            let tok = self.t.next();
+            return Err(self.t.unexpected(tok.as_ref(), &["`{`", "`[`"]));
+        }
+        self.build_value(b)
+    }
+
+    // Like try_parse, but only whitespace (and comments, if allowed) may follow the value
+    fn parse_document(&mut self) -> Result<Value, ParseError> {
+        let mut tree = Tree { duplicate_keys: self.options.duplicate_keys };
+        self.build_document(&mut tree)
+    }
+
+    fn build_document<B: Build>(&mut self, b: &mut B) -> Result<B::Value, ParseError> {
+        let val = self.build_root(b).map_err(|e| self.locate(e))?;
+        match self.t.next() {
+            None if self.t.t.error().is_none() => Ok(val),
+            tok => Err(self.locate(self.t.unexpected(tok.as_ref(), &["end of input"]))),
+        }
+    }
+
+    // Checks that the input is a valid document like parse_document does, with the same
+    // options, but only counts what the tree would hold instead of building it, to plan
+    // for its memory
+    pub fn estimate_size(mut self) -> Result<SizeEstimate, ParseError> {
+        let mut counter = Counter {
+            estimate: SizeEstimate::default(),
+            duplicate_keys: self.options.duplicate_keys,
+        };
+        self.build_document(&mut counter)?;
+        Ok(counter.estimate)
+    }
+
+    // Parses with comments allowed and returns them alongside the value, attached to the
+    // value they precede or, if on the same line, follow. Value::to_string_with_comments
+    // writes them back out
+    pub fn parse_with_comments(mut self) -> Result<(Value, Comments), ParseError> {
+        self.t.t.options.allow_comments = true;
+        self.t.t.collect_comments = true;
+        self.comments = Some(Comments::new());
+
+        let val = self.parse_root().map_err(|e| self.locate(e))?;
+        self.attach_comments(None);
+        Ok((val, self.comments.unwrap_or_default()))
+    }
+
+    // JSONC: parses with comments allowed and returns every comment, delimiters included,
+    // with where it starts. Unlike parse_with_comments they aren't tied to values
+    pub fn parse_collecting_comments(mut self) -> Result<(Value, Vec<(Position, String)>), ParseError> {
+        self.t.t.options.allow_comments = true;
+        self.t.t.collect_comments = true;
+
+        let val = self.parse_root().map_err(|e| self.locate(e))?;
+        // Looking for another token picks up the comments after the value
+        self.t.peek();
+        let comments = self.t.t.comments.drain(..).map(|comment| (comment.position, comment.text)).collect();
+        Ok((val, comments))
+    }
+
+    // Warnings about what was parsed so far, e.g. after parse_one
+    pub fn warnings(&self) -> &[Warning] {
+        self.t.t.warnings()
+    }
+
+    // Like try_parse, but also hands back the warnings, including those from before an error
+    pub fn parse_with_warnings(mut self) -> (Result<Value, ParseError>, Vec<Warning>) {
+        let result = self.parse_root().map_err(|e| self.locate(e));
+        (result, std::mem::take(&mut self.t.t.warnings))
+    }
+
+    // true if nothing but whitespace (and comments, if allowed) is left
+    pub fn is_exhausted(&mut self) -> bool {
+        self.t.peek().is_none() && self.t.t.error().is_none()
+    }
+
+    // Points the error at the start of the last token, which for tokenizer errors is
+    // where the malformed token begins
+    fn locate(&self, e: ParseError) -> ParseError {
+        self.locate_at(e, self.t.start)
+    }
+
+    fn locate_at(&self, e: ParseError, offset: usize) -> ParseError {
+        match (self.input, e) {
+            (_, e @ ParseError::Located { .. }) => e,
+            (Some(input), e) => e.located(input, offset),
+            (None, e) => e,
+        }
+    }
+
+    // Checks the value that tok starts against the schema of parse_validated, if there is
+    // one. Objects and arrays are checked for their type here, their contents as they are read
+    fn check_schema(&self, tok: Option<&Token>) -> Result<(), ParseError> {
+        let schema = match self.schema.as_ref().and_then(|schema| schema.at(&self.path)) {
+            Some(schema) => schema,
+            None => return Ok(()),
+        };
+        let empty;
+        let found = match tok {
+            Some(Token::Value(val)) => val,
+            Some(Token::CurlyBracketOpen) => {
+                empty = Object(HashMap::new());
+                &empty
+            },
+            Some(Token::BracketOpen) => {
+                empty = Array(Vec::new());
+                &empty
+            },
+            _ => return Ok(()),
+        };
+        schema.admits(found).map_err(|e| self.at_path(e, &self.path, self.t.start))
+    }
+
+    // Checks that an object that started at start has every key its schema requires
+    fn check_required(&self, keys: &HashSet<String>, start: usize) -> Result<(), ParseError> {
+        let fields = match self.schema.as_ref().and_then(|schema| schema.at(&self.path)) {
+            Some(Schema::Object(fields)) => fields,
+            _ => return Ok(()),
+        };
+        match fields.iter().find(|(key, field)| field.required && !keys.contains(*key)) {
+            Some((key, _)) => {
+                let mut path = self.path.clone();
+                path.push(key.clone());
+                Err(self.at_path(ParseError::KeyNotFound(key.clone()), &path, start))
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn at_path(&self, e: ParseError, path: &[String], offset: usize) -> ParseError {
+        self.locate_at(ParseError::AtPath { error: Box::new(e), path: to_pointer(path) }, offset)
+    }
+
+    // Distributes the comments before the next token: those on the line of the previous
+    // value trail it, everything after that leads the value at `next`
+    fn attach_comments(&mut self, next: Option<String>) {
+        if self.comments.is_none() {
+            return;
+        }
+
+        let mut leading = false;
+        for comment in self.t.take_comments() {
+            leading |= comment.own_line || self.last_value.is_none();
+            let (pointer, is_leading) = match (&next, &self.last_value) {
+                (Some(next), _) if leading => (next.clone(), true),
+                (_, Some(last)) => (last.clone(), false),
+                // Nothing before or after it inside the container, keep it on the container
+                _ => (to_pointer(&self.path), false),
+            };
+
+            if let Some(comments) = self.comments.as_mut() {
+                let entry = comments.entry(pointer).or_default();
+                if is_leading {
+                    entry.leading.push(comment.text);
+                } else {
+                    entry.trailing.push(comment.text);
+                }
+            }
+        }
+    }
+
+    fn enter(&mut self, segment: impl FnOnce() -> String) {
+        if self.comments.is_some() || self.schema.is_some() {
+            self.path.push(segment());
+        }
+    }
+
+    fn leave(&mut self) {
+        self.path.pop();
+    }
+
+    // Runs f one nesting level deeper, failing if that exceeds max_depth. build_object
+    // and build_array are entered with their opening bracket already consumed
+    fn nested<B: Build>(
+        &mut self,
+        b: &mut B,
+        f: fn(&mut Self, &mut B) -> Result<B::Value, ParseError>,
+    ) -> Result<B::Value, ParseError> {
+        self.depth += 1;
+        let max = self.options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        if self.depth > max {
+            return Err(ParseError::MaxDepthExceeded(max));
+        }
+
+        let val = f(self, b);
+        self.depth -= 1;
+        val
+    }
+
+    // Continues as if a `,` preceded tok, which is read again as the next element
+    fn missing_comma(&mut self, tok: Token) {
+        self.t.t.warnings.push(Warning::MissingComma { offset: self.t.start });
+        self.t.unread(tok);
+    }
+
+    // Consumes the closing token after a `,` if trailing commas are allowed
+    fn trailing_comma_closes(&mut self, close: fn(&Token) -> bool) -> bool {
+        if self.options.allow_trailing_commas {
+            if let Some(tok) = self.t.peek() {
+                if close(tok) {
+                    self.attach_comments(None);
+                    self.t.next();
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    fn build_object<B: Build>(&mut self, b: &mut B) -> Result<B::Value, ParseError> {
+        let mut object = b.object();
+        // Where the object starts and, with a schema, which keys it has
+        let start = self.t.start;
+        let mut keys = HashSet::new();
+
+        self.last_value = None;
+
+        if let Some(Token::CurlyBracketClose) = self.t.peek() {
+            self.attach_comments(None);
+            self.t.next();
+            return self.end_object(b, object, &keys, start);
+        }
+
+        let mut expected_key: &[&str] = &["a string key", "`}`"];
+        loop {
+            if self.comments.is_some() {
+                let json5 = self.options.json5;
+                let key = match self.t.peek() {
+                    Some(Token::Value(JsonString(key))) => Some(key.clone()),
+                    Some(tok) if json5 => numeric_key(tok),
+                    _ => None,
+                };
+                if let Some(key) = key {
+                    self.enter(|| key);
+                    self.attach_comments(Some(to_pointer(&self.path)));
+                    self.leave();
+                }
+            }
+
+            // Consuming key, only strings are valid keys, and in JSON5 mode numbers
+            let mut key = match self.t.next() {
+                Some(Token::Value(JsonString(s))) => s,
+                tok => match tok.as_ref().and_then(numeric_key).filter(|_| self.options.json5) {
+                    Some(key) => {
+                        self.t.t.warnings.push(Warning::NumericKey { key: key.clone(), offset: self.t.start });
+                        key
+                    },
+                    None => return Err(self.t.unexpected(tok.as_ref(), expected_key)),
+                },
+            };
+            self.last_value = None;
+            // After the first entry a `}` is no longer valid in key position
+            expected_key = &["a string key"];
+
+            // Consuming :
+            match self.t.next() {
+                Some(Token::Colon) => {},
+                tok => return Err(self.t.unexpected(tok.as_ref(), &["`:`"])),
+            }
+
+            self.enter(|| key.clone());
+            let val = self.build_value(b)?;
+            self.leave();
+            self.attach_comments(None);
+
+            if self.schema.is_some() {
+                keys.insert(key.clone());
+            }
+            b.insert(&mut object, &mut key, val)?;
+            self.t.t.recycle(key);
+
+            // Consuming , or }
+            match self.t.next() {
+                Some(Token::Comma) => {
+                    if self.trailing_comma_closes(|tok| matches!(tok, Token::CurlyBracketClose)) {
+                        return self.end_object(b, object, &keys, start);
+                    }
+                    continue
+                },
+                Some(Token::CurlyBracketClose) => return self.end_object(b, object, &keys, start),
+                Some(tok @ Token::Value(JsonString(_))) if self.options.recover_missing_commas => {
+                    self.missing_comma(tok);
+                },
+                tok => return Err(self.t.unexpected(tok.as_ref(), &["`,`", "`}`"])),
+            }
+        }
+    }
+
+    fn end_object<B: Build>(
+        &self,
+        b: &mut B,
+        object: B::Object,
+        keys: &HashSet<String>,
+        start: usize,
+    ) -> Result<B::Value, ParseError> {
+        if self.schema.is_some() {
+            self.check_required(keys, start)?;
+        }
+        Ok(b.end_object(object))
+    }
+
+    fn parse_value(&mut self) -> Result<Value, ParseError> {
+        let mut tree = Tree { duplicate_keys: self.options.duplicate_keys };
+        self.build_value(&mut tree)
+    }
+
+    fn build_value<B: Build>(&mut self, b: &mut B) -> Result<B::Value, ParseError> {
+        if self.comments.is_some() {
+            self.attach_comments(Some(to_pointer(&self.path)));
+        }
+
+        let tok = self.t.next();
+        if self.schema.is_some() {
+            self.check_schema(tok.as_ref())?;
+        }
+        let val = match tok {
+            Some(Token::CurlyBracketOpen) => self.nested(b, Self::build_object),
+            Some(Token::BracketOpen) => self.nested(b, Self::build_array),
+            Some(Token::Value(JsonString(mut s))) => {
+                let val = b.string(&mut s);
+                self.t.t.recycle(s);
+                Ok(val)
+            },
+            Some(Token::Value(val)) => Ok(b.scalar(val)),
+            tok => Err(self.t.unexpected(tok.as_ref(), &["a value"])),
+        }?;
+
+        if self.comments.is_some() {
+            self.last_value = Some(to_pointer(&self.path));
+        }
+        Ok(val)
+    }
+
+    fn build_array<B: Build>(&mut self, b: &mut B) -> Result<B::Value, ParseError> {
+        let mut array = b.array();
+        let mut len = 0;
+
+        self.last_value = None;
+
+        if let Some(Token::BracketClose) = self.t.peek() {
+            self.attach_comments(None);
+            self.t.next();
+            return Ok(b.end_array(array));
+        }
+
+        loop {
+            self.enter(|| len.to_string());
+            let val = self.build_value(b)?;
+            self.leave();
+            self.attach_comments(None);
+            b.push(&mut array, val);
+            len += 1;
+
+            // Consuming , or ]
+            match self.t.next() {
+                Some(Token::Comma) => {
+                    if self.trailing_comma_closes(|tok| matches!(tok, Token::BracketClose)) {
+                        return Ok(b.end_array(array));
+                    }
+                    continue
+                },
+                Some(Token::BracketClose) => return Ok(b.end_array(array)),
+                Some(tok) if self.options.recover_missing_commas && starts_value(&tok) => self.missing_comma(tok),
+                tok => return Err(self.t.unexpected(tok.as_ref(), &["`,`", "`]`"])),
+            }
+        }
+    }
+}
+
+// Where the parser puts what it reads: Tree builds the owned Value tree, Counter only counts
+// for estimate_size and the arena feature allocates into a Bump. String contents and keys
+// come as &mut String, which a builder either takes or copies from. The parser reads the
+// next string into whatever is left, so that copying doesn't cost an allocation per string
+trait Build {
+    type Value;
+    type Array;
+    type Object;
+
+    // A scalar from the tokenizer, or whatever a number_hook made of a number
+    fn scalar(&mut self, val: Value) -> Self::Value;
+    fn string(&mut self, s: &mut String) -> Self::Value;
+    fn array(&mut self) -> Self::Array;
+    fn push(&mut self, array: &mut Self::Array, val: Self::Value);
+    fn end_array(&mut self, array: Self::Array) -> Self::Value;
+    fn object(&mut self) -> Self::Object;
+    // Resolves a repeated key as ParserOptions::duplicate_keys says
+    fn insert(&mut self, object: &mut Self::Object, key: &mut String, val: Self::Value) -> Result<(), ParseError>;
+    fn end_object(&mut self, object: Self::Object) -> Self::Value;
+}
+
+struct Tree {
+    duplicate_keys: DuplicateKeys,
+}
+
+impl Build for Tree {
+    type Value = Value;
+    type Array = Vec<Value>;
+    // The entries, and the keys already turned into arrays by DuplicateKeys::Collect
+    type Object = (HashMap<String, Value>, HashSet<String>);
+
+    fn scalar(&mut self, val: Value) -> Value {
+        val
+    }
+
+    fn string(&mut self, s: &mut String) -> Value {
+        JsonString(std::mem::take(s))
+    }
+
+    fn array(&mut self) -> Vec<Value> {
+        Vec::new()
+    }
+
+    fn push(&mut self, array: &mut Vec<Value>, val: Value) {
+        array.push(val);
+    }
+
+    fn end_array(&mut self, array: Vec<Value>) -> Value {
+        Array(array)
+    }
+
+    fn object(&mut self) -> Self::Object {
+        (HashMap::new(), HashSet::new())
+    }
+
+    fn insert(&mut self, (map, collected): &mut Self::Object, key: &mut String, val: Value) -> Result<(), ParseError> {
+        insert_entry(map, collected, std::mem::take(key), val, self.duplicate_keys)
+    }
+
+    fn end_object(&mut self, (map, _): Self::Object) -> Value {
+        Object(map)
+    }
+}
+
+// Keeps nothing but the keys of an object, and those only for DuplicateKeys::Error to
+// see repeats
+struct Counter {
+    estimate: SizeEstimate,
+    duplicate_keys: DuplicateKeys,
+}
+
+impl Build for Counter {
+    type Value = ();
+    type Array = ();
+    type Object = HashSet<String>;
+
+    fn scalar(&mut self, _val: Value) {
+        self.estimate.values += 1;
+    }
+
+    fn string(&mut self, s: &mut String) {
+        self.estimate.values += 1;
+        self.estimate.strings += 1;
+        self.estimate.string_bytes += s.len();
+    }
+
+    fn array(&mut self) {}
+
+    fn push(&mut self, _array: &mut (), _val: ()) {}
+
+    fn end_array(&mut self, _array: ()) {
+        self.estimate.values += 1;
+        self.estimate.arrays += 1;
+    }
+
+    fn object(&mut self) -> HashSet<String> {
+        HashSet::new()
+    }
+
+    fn insert(&mut self, keys: &mut HashSet<String>, key: &mut String, _val: ()) -> Result<(), ParseError> {
+        self.estimate.strings += 1;
+        self.estimate.string_bytes += key.len();
+        if self.duplicate_keys == DuplicateKeys::Error && !keys.insert(key.clone()) {
+            return Err(ParseError::DuplicateKey(std::mem::take(key)));
+        }
+        Ok(())
+    }
+
+    fn end_object(&mut self, _keys: HashSet<String>) {
+        self.estimate.values += 1;
+        self.estimate.objects += 1;
+    }
+}
+
+// Adds an object entry, resolving a repeated key as the policy says. collected holds the
+// keys already turned into arrays by DuplicateKeys::Collect
+fn insert_entry(
+    map: &mut HashMap<String, Value>,
+    collected: &mut HashSet<String>,
+    key: String,
+    val: Value,
+    policy: DuplicateKeys,
+) -> Result<(), ParseError> {
+    match policy {
+        DuplicateKeys::TakeLast => {
+            map.insert(key, val);
+        },
+        DuplicateKeys::TakeFirst => {
+            map.entry(key).or_insert(val);
+        },
+        // One lookup for both the check and the insert
+        DuplicateKeys::Error => match map.entry(key) {
+            hash_map::Entry::Occupied(entry) => {
+                return Err(ParseError::DuplicateKey(entry.key().clone()));
+            },
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(val);
+            },
+        },
+        // The first repeat wraps the stored value into an array, later ones append
+        DuplicateKeys::Collect => match map.entry(key) {
+            hash_map::Entry::Occupied(mut entry) => {
+                let first_repeat = collected.insert(entry.key().clone());
+                let stored = entry.get_mut();
+                if first_repeat {
+                    let first = std::mem::replace(stored, Null);
+                    *stored = Array(vec![first, val]);
+                } else if let Array(v) = stored {
+                    v.push(val);
+                }
+            },
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(val);
+            },
+        },
+    }
+    Ok(())
+}
+
+// The elements of a top-level array, each parsed only when it is asked for, so a few
+// elements can be taken out of a large array without building all the others. Nothing
+// is cached, getting an element twice parses it twice
+pub struct LazyArray<'a> {
+    input: &'a str,
+    options: ParserOptions,
+    // Byte range of each element in input, up to the `,` or `]` after it, and the char
+    // offset the element starts at
+    elements: Vec<(std::ops::Range<usize>, usize)>,
+}
+
+impl<'a> LazyArray<'a> {
+    // Scans input, which has to be an array, only far enough to find where each of its
+    // elements is. Within an element the tokens are read but only its brackets are
+    // checked, anything else wrong with it surfaces when the element is parsed
+    pub fn parse(input: &'a str, options: ParserOptions) -> Result<LazyArray<'a>, ParseError> {
+        let mut parser = Parser::with_options(input, options);
+        let spans = parser.scan_elements().map_err(|e| parser.locate(e))?;
+
+        // The char offsets only increase, so a single pass turns them into byte offsets.
+        // They are offsets into input itself, or just past its end, so each is found
+        let mut bytes = input.char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(input.len()))
+            .enumerate();
+        let mut to_byte = |offset: usize| bytes.find(|&(c, _)| c == offset).unwrap().1;
+        let elements = spans.into_iter()
+            .map(|(start, end)| (to_byte(start)..to_byte(end), start))
+            .collect();
+
+        // Elements are parsed on their own, where a scalar is the root
+        let mut options = parser.options;
+        options.require_compound_root = false;
+        Ok(LazyArray { input, options, elements })
+    }
+
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    // The text of the element at index as it is in the input, including any whitespace
+    // or comments after it
+    pub fn raw(&self, index: usize) -> Option<&'a str> {
+        let (range, _) = self.elements.get(index)?;
+        Some(&self.input[range.clone()])
+    }
+
+    // Parses the element at index, None if there is no such element. Errors are located
+    // within the whole input
+    pub fn get(&self, index: usize) -> Option<Result<Value, ParseError>> {
+        let raw = self.raw(index)?;
+        let start = self.elements[index].1;
+        let val = Parser::with_options(raw, self.options.clone()).parse_document();
+        Some(val.map_err(|e| match e {
+            ParseError::Located { error, offset, .. } => error.located(self.input, start + offset),
+            e => e,
+        }))
+    }
+
+    // Parses every element in order
+    pub fn iter(&self) -> impl Iterator<Item = Result<Value, ParseError>> + '_ {
+        (0..self.len()).map(move |i| self.get(i).unwrap())
+    }
+}
+
+// Parses a document handed over in chunks as they arrive, e.g. reads from a socket.
+// Complete tokens are consumed as soon as they are seen and only an unfinished token at
+// the end of a chunk (a number, a string or a UTF-8 sequence cut in two) is carried over
+// to the next feed. Nesting is tracked on an explicit stack rather than by recursion
+pub struct PushParser {
+    options: ParserOptions,
+    // Start of a token that may continue in the next chunk, and the bytes of a char that does
+    pending: String,
+    partial_char: Vec<u8>,
+    // The token pending starts with, if chunks that can't end it needn't scan it again
+    unfinished: Option<Unfinished>,
+    bytes_fed: usize,
+    // Char offset of the start of pending in the whole input
+    offset: usize,
+    stack: Vec<Frame>,
+    expect: Expect,
+    root: Option<Value>,
+    tokens: usize,
+    warnings: Vec<Warning>,
+    error: Option<ParseError>,
+}
+
+// A container that is still open
+enum Frame {
+    Array(Vec<Value>),
+    Object {
+        map: HashMap<String, Value>,
+        collected: HashSet<String>,
+        key: Option<String>,
+    },
+}
+
+// What the next token may be
+#[derive(Clone, Copy, PartialEq)]
+enum Expect {
+    Value,
+    ValueOrClose,
+    Key,
+    KeyOrClose,
+    Colon,
+    CommaOrClose,
+    End,
+}
+
+// A string or number cut off at the end of pending. Pending is only scanned again once a
+// chunk may end it, so a long token arriving in many chunks isn't tokenized over and over
+enum Unfinished {
+    // escaped is whether the string so far ends in a backslash that escapes the next char
+    String { escaped: bool },
+    Number,
+}
+
+impl Unfinished {
+    fn of(pending: &str) -> Option<Unfinished> {
+        let mut chars = pending.trim_start().chars();
+        match chars.next()? {
+            '"' => {
+                let mut string = Unfinished::String { escaped: false };
+                // A string that is already closed just ended with the chunk
+                if string.ends_in(chars.as_str()) {
+                    None
+                } else {
+                    Some(string)
+                }
+            },
+            '-' | '+' | '.' | '0'..='9' => Some(Unfinished::Number),
+            _ => None,
+        }
+    }
+
+    // Whether the token may end within more, which follows what has been seen of it
+    fn ends_in(&mut self, more: &str) -> bool {
+        match self {
+            Unfinished::String { escaped } => more.chars().any(|c| {
+                let closes = c == '"' && !*escaped;
+                *escaped = c == '\\' && !*escaped;
+                closes
+            }),
+            // Anything that can't continue a literal, with its sign, exponent and
+            // separators. Letters are read into the literal too, to reject e.g. 0xFF
+            Unfinished::Number => !more.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-' | '_')),
+        }
+    }
+}
+
+impl Default for PushParser {
+    fn default() -> PushParser {
+        PushParser::new()
+    }
+}
+
+impl PushParser {
+    pub fn new() -> PushParser {
+        PushParser::with_options(ParserOptions::default())
+    }
+
+    pub fn with_options(options: ParserOptions) -> PushParser {
+        PushParser {
+            options,
+            pending: String::new(),
+            partial_char: Vec::new(),
+            unfinished: None,
+            bytes_fed: 0,
+            offset: 0,
+            stack: Vec::new(),
+            expect: Expect::Value,
+            root: None,
+            tokens: 0,
+            warnings: Vec::new(),
+            error: None,
+        }
+    }
+
+    // After an error further chunks are ignored, finish returns the error
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let mut bytes = std::mem::take(&mut self.partial_char);
+        bytes.extend_from_slice(chunk);
+        let start = self.bytes_fed;
+        self.bytes_fed += chunk.len();
+
+        let valid = match std::str::from_utf8(&bytes) {
+            Ok(s) => s,
+            Err(e) => {
+                // No error_len means the input just stops in the middle of a char
+                if e.error_len().is_some() {
+                    self.error = Some(ParseError::InvalidUtf8 { offset: start + e.valid_up_to() });
+                    return;
+                }
+                self.partial_char = bytes[e.valid_up_to()..].to_vec();
+                std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap()
+            },
+        };
+        let ends = self.unfinished.as_mut().is_none_or(|token| token.ends_in(valid));
+        self.pending.push_str(valid);
+        if !ends {
+            return;
+        }
+
+        if let Err(e) = self.scan(false) {
+            self.error = Some(e);
+        }
+    }
+
+    // The reason parsing failed, if it already has
+    pub fn error(&self) -> Option<&ParseError> {
+        self.error.as_ref()
+    }
+
+    // Lossy number conversions and recovered problems so far
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    // Whether a whole document has been seen. A top-level number can't be known to be
+    // complete before finish, as the next chunk might hold more of its digits
+    pub fn is_complete(&self) -> bool {
+        self.root.is_some()
+    }
+
+    pub fn finish(mut self) -> Result<Value, ParseError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        if !self.partial_char.is_empty() {
+            return Err(ParseError::InvalidUtf8 { offset: self.bytes_fed - self.partial_char.len() });
+        }
+
+        self.scan(true)?;
+        match self.root {
+            Some(val) => Ok(val),
+            None => Err(unexpected(None, self.expected())),
+        }
+    }
+
+    // Consumes the complete tokens of pending. Unless at_end, a token running up to the
+    // end of pending is left there, as it may continue in the next chunk
+    fn scan(&mut self, at_end: bool) -> Result<(), ParseError> {
+        let options = ParserOptions { max_tokens: None, ..self.options.clone() };
+        let pending = std::mem::take(&mut self.pending);
+        let mut t = Tokenizer::with_options(&pending, &options);
+        let len = pending.chars().count();
+        // Char offset up to which pending has been consumed
+        let mut consumed = 0;
+
+        loop {
+            let tok = t.next_token();
+            let cut_off = !at_end && t.offset() == len;
+            match tok {
+                Some(Token::Value(_)) if cut_off => break,
+                Some(tok) => {
+                    consumed = t.offset();
+                    self.push_token(tok, self.offset + t.token_start)?;
+                },
+                // Trailing whitespace stays too, it could be the start of a comment
+                None => match t.error() {
+                    // Whatever failed ran into the end of the chunk, the next one may complete it
+                    Some(e) if !cut_off => return Err(e.clone()),
+                    _ => break,
+                },
+            }
+        }
+
+        // Tokens left in pending are scanned again, so are their warnings
+        let base = self.offset;
+        self.warnings.extend(t.warnings().iter().filter_map(|warning| match warning.clone() {
+            Warning::IntegerPromoted { literal, offset } if offset < consumed => {
+                Some(Warning::IntegerPromoted { literal, offset: base + offset })
+            },
+            Warning::PrecisionLoss { literal, offset } if offset < consumed => {
+                Some(Warning::PrecisionLoss { literal, offset: base + offset })
+            },
+            _ => None,
+        }));
+
+        let consumed_bytes = pending.char_indices().nth(consumed).map_or(pending.len(), |(i, _)| i);
+        self.pending = pending[consumed_bytes..].to_string();
+        self.offset += consumed;
+        self.unfinished = Unfinished::of(&self.pending);
+        Ok(())
+    }
+
+    // offset is the char offset of tok in the whole input
+    fn push_token(&mut self, tok: Token, offset: usize) -> Result<(), ParseError> {
+        self.tokens += 1;
+        if let Some(max) = self.options.max_tokens.filter(|max| self.tokens > *max) {
+            return Err(ParseError::BudgetExceeded(max));
+        }
+
+        let in_array = matches!(self.stack.last(), Some(Frame::Array(_)));
+        if self.expect == Expect::CommaOrClose && self.options.recover_missing_commas {
+            let starts_element = if in_array { starts_value(&tok) } else { matches!(tok, Token::Value(JsonString(_))) };
+            if starts_element {
+                self.warnings.push(Warning::MissingComma { offset });
+                self.expect = if in_array { Expect::Value } else { Expect::Key };
+            }
+        }
+
+        match (self.expect, tok) {
+            (Expect::Value | Expect::ValueOrClose, Token::CurlyBracketOpen) => {
+                self.open(Frame::Object { map: HashMap::new(), collected: HashSet::new(), key: None })?;
+                self.expect = Expect::KeyOrClose;
+            },
+            (Expect::Value | Expect::ValueOrClose, Token::BracketOpen) => {
+                self.open(Frame::Array(Vec::new()))?;
+                self.expect = Expect::ValueOrClose;
+            },
+            (Expect::Value, tok @ Token::Value(_)) if self.stack.is_empty() && self.options.require_compound_root => {
+                return Err(unexpected(Some(&tok), &["`{`", "`[`"]));
+            },
+            (Expect::Value | Expect::ValueOrClose, Token::Value(val)) => self.complete(val)?,
+            (Expect::Key | Expect::KeyOrClose, Token::Value(JsonString(key))) => self.key(key),
+            (Expect::Key | Expect::KeyOrClose, tok) if self.options.json5 && numeric_key(&tok).is_some() => {
+                let key = numeric_key(&tok).unwrap();
+                self.warnings.push(Warning::NumericKey { key: key.clone(), offset });
+                self.key(key);
+            },
+            (Expect::Colon, Token::Colon) => self.expect = Expect::Value,
+            (Expect::CommaOrClose, Token::Comma) => {
+                let trailing = self.options.allow_trailing_commas;
+                self.expect = match (self.stack.last(), trailing) {
+                    (Some(Frame::Array(_)), false) => Expect::Value,
+                    (Some(Frame::Array(_)), true) => Expect::ValueOrClose,
+                    (_, false) => Expect::Key,
+                    (_, true) => Expect::KeyOrClose,
+                };
+            },
+            (Expect::ValueOrClose | Expect::CommaOrClose, Token::BracketClose)
+                if matches!(self.stack.last(), Some(Frame::Array(_))) => self.close()?,
+            (Expect::KeyOrClose | Expect::CommaOrClose, Token::CurlyBracketClose)
+                if matches!(self.stack.last(), Some(Frame::Object { .. })) => self.close()?,
+            (_, tok) => return Err(unexpected(Some(&tok), self.expected())),
+        }
+        Ok(())
+    }
+
+    fn key(&mut self, key: String) {
+        if let Some(Frame::Object { key: slot, .. }) = self.stack.last_mut() {
+            *slot = Some(key);
+        }
+        self.expect = Expect::Colon;
+    }
+
+    fn open(&mut self, frame: Frame) -> Result<(), ParseError> {
+        let max = self.options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        if self.stack.len() >= max {
+            return Err(ParseError::MaxDepthExceeded(max));
+        }
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), ParseError> {
+        let val = match self.stack.pop() {
+            Some(Frame::Array(v)) => Array(v),
+            Some(Frame::Object { map, .. }) => Object(map),
+            None => return Ok(()),
+        };
+        self.complete(val)
+    }
+
+    // Puts a finished value into the innermost open container, or makes it the document
+    fn complete(&mut self, val: Value) -> Result<(), ParseError> {
+        let policy = self.options.duplicate_keys;
+        match self.stack.last_mut() {
+            Some(Frame::Array(v)) => v.push(val),
+            Some(Frame::Object { map, collected, key }) => {
+                let key = key.take().unwrap_or_default();
+                insert_entry(map, collected, key, val, policy)?;
+            },
+            None => {
+                self.root = Some(val);
+                self.expect = Expect::End;
+                return Ok(());
+            },
+        }
+        self.expect = Expect::CommaOrClose;
+        Ok(())
+    }
+
+    fn expected(&self) -> &'static [&'static str] {
+        match self.expect {
+            Expect::Value | Expect::ValueOrClose => &["a value"],
+            Expect::Key => &["a string key"],
+            Expect::KeyOrClose => &["a string key", "`}`"],
+            Expect::Colon => &["`:`"],
+            Expect::CommaOrClose => match self.stack.last() {
+                Some(Frame::Array(_)) => &["`,`", "`]`"],
+                _ => &["`,`", "`}`"],
+            },
+            Expect::End => &["end of input"],
+        }
+    }
+}
+
+// The string a number token stands for as a JSON5 object key, its value written out the
+// way JavaScript does for ordinary numbers, e.g. 0x10 is "16" and 1.50 is "1.5". A Number
+// keeps its literal
+fn numeric_key(tok: &Token) -> Option<String> {
+    match tok {
+        Token::Value(Int(i)) => Some(i.to_string()),
+        Token::Value(BigInt(b)) => Some(b.to_string()),
+        Token::Value(Float(fl)) => Some(fl.to_string()),
+        Token::Value(Number { raw, .. }) => Some(raw.clone()),
+        _ => None,
+    }
+}
+
+// Whether tok can begin a value, and so an array element
+fn starts_value(tok: &Token) -> bool {
+    matches!(tok, Token::Value(_) | Token::CurlyBracketOpen | Token::BracketOpen)
+}
+
+// Error for finding `found` where one of `expected` would have been valid
+fn unexpected(found: Option<&Token>, expected: &[&str]) -> ParseError {
+    let expected = match expected {
+        [] => String::from("nothing"),
+        [only] => only.to_string(),
+        [init @ .., last] => format!("one of {} or {}", init.join(", "), last),
+    };
+
+    match found {
+        Some(tok) => ParseError::UnexpectedToken {
+            found: format!("{:?}", tok),
+            expected,
+        },
+        None => ParseError::UnexpectedEof { expected },
+    }
+}
+
+// Extending Option<&Value> and Option<Value> to provide some sugar to work with Value
+pub trait OptionValueExt {
+    fn get_arr(&self, i: usize) -> Option<&Value>;
+    fn get_map(&self, key: &str) -> Option<&Value>;
+    // true only if the value exists and is null, false if it is missing
+    fn is_present_null(&self) -> bool;
+    // Like get_map, but a missing key (or missing parent) yields Null so the chain can go on
+    fn get_or_null(&self, key: &str) -> &Value;
+}
+
+static NULL: Value = Null;
+
+impl OptionValueExt for Option<&Value> {
+    fn get_arr(&self, i: usize) -> Option<&Value> {
+        match self {
+            Some(val) => {
+                val.get_arr(i)
+            }
+            _ => None
+        }
+    }
+
+    fn get_map(&self, key: &str) -> Option<&Value> {
+        match self {
+            Some(val) => {
+                val.get_map(key)
+            }
+            _ => None
+        }
+    }
+
+    fn is_present_null(&self) -> bool {
+        self.is_some_and(|val| matches!(val.resolve(), Null))
+    }
+
+    fn get_or_null(&self, key: &str) -> &Value {
+        self.get_map(key).unwrap_or(&NULL)
+    }
+}
+
+// The same sugar directly on an owned parse result, without going through as_ref()
+impl OptionValueExt for Option<Value> {
+    fn get_arr(&self, i: usize) -> Option<&Value> {
+        self.as_ref().and_then(|val| val.get_arr(i))
+    }
+
+    fn get_map(&self, key: &str) -> Option<&Value> {
+        self.as_ref().and_then(|val| val.get_map(key))
+    }
+
+    fn is_present_null(&self) -> bool {
+        self.as_ref().is_present_null()
+    }
+
+    fn get_or_null(&self, key: &str) -> &Value {
+        self.get_map(key).unwrap_or(&NULL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_variants_need_no_annotations() {
+        let v = Int(1);
+        assert_eq!(v.to_string(), "1");
+        assert_eq!(format!("{}", Value::Float(1.0)), "1.0");
+    }
+
+    #[test]
+    fn into_keys_round_trips() {
+        let v: Value = r#"{"a": [1, {"b": null}], "c": "d"}"#.parse().unwrap();
+        let boxed = v.clone().into_keys::<Box<str>>();
+        assert_eq!(boxed.get_map("a").and_then(|a| a.get_arr(1)).map(|b| b.to_string()), Some(r#"{"b":null}"#.to_string()));
+        assert_eq!(boxed.to_string().parse::<Value>().unwrap(), v);
+        assert_eq!(boxed.into_value(), v);
+    }
+
+    // xorshift64, a fixed seed keeps failures reproducible
+    fn random_bits(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn display_round_trips_ints_and_floats() {
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        let mut values = vec![Int(0), Int(i64::MIN), Int(i64::MAX), Float(0.0), Float(-0.0), Float(1.0), Float(f64::MAX), Float(f64::MIN_POSITIVE)];
+        for _ in 0..10_000 {
+            let bits = random_bits(&mut state);
+            values.push(Int(bits as i64));
+            let fl = f64::from_bits(random_bits(&mut state));
+            if fl.is_finite() {
+                values.push(Float(fl));
+            }
+            // Small magnitudes are where `1` and `1.0` could be confused
+            values.push(Float((bits % 2000) as f64 - 1000.0));
+        }
+
+        for val in values {
+            let parsed: Value = val.to_string().parse().unwrap();
+            match (&val, &parsed) {
+                (Int(a), Int(b)) => assert_eq!(a, b),
+                (Float(a), Float(b)) => assert_eq!(a.to_bits(), b.to_bits(), "{}", val),
+                _ => panic!("{:?} read back as {:?}", val, parsed),
+            }
+        }
+    }
+
+    #[test]
+    fn floats_use_an_exponent_where_shorter() {
+        let shortest = SerializeOptions::new().float_format(FloatFormat::Shortest);
+        for (fl, expected) in [(1e300, "1e300"), (5e-324, "5e-324"), (-1.5e-10, "-1.5e-10"), (100.0, "100"), (0.5, "0.5")] {
+            assert_eq!(Float(fl).to_string_with(&shortest), expected);
+        }
+        let defaults = [
+            (1e300, "1e300"), (1e21, "1e21"), (1e20, "100000000000000000000.0"), (1e6, "1000000.0"),
+            (1000.0, "1000.0"), (0.001, "0.001"), (1e-6, "0.000001"), (1e-7, "1e-7"), (0.0, "0.0"), (2.5, "2.5"),
+        ];
+        for (fl, expected) in defaults {
+            assert_eq!(Float(fl).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn non_finite_policies() {
+        let nan = Float(f64::NAN);
+        let options = SerializeOptions::new();
+        let err = nan.try_to_string_with(&options).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(Float(f64::INFINITY).to_writer(Vec::new()).is_err());
+
+        assert_eq!(nan.try_to_string_with(&options.clone().non_finite(NonFinite::Null)).unwrap(), "null");
+        assert_eq!(nan.try_to_string_with(&options.clone().non_finite(NonFinite::String)).unwrap(), r#""NaN""#);
+        let infinities = Array(vec![Float(f64::INFINITY), Float(f64::NEG_INFINITY)]);
+        assert_eq!(infinities.try_to_string_with(&options.non_finite(NonFinite::String)).unwrap(), r#"["Infinity","-Infinity"]"#);
+    }
+
+    #[test]
+    fn infallible_serializations_write_null_for_non_finite() {
+        let v = Array(vec![Float(f64::NAN)]);
+        assert_eq!(v.to_string(), "[null]");
+        assert_eq!(format!("{:#}", v), "[\n  null\n]");
+        assert_eq!(v.to_bytes(), b"[null]");
+        assert_eq!(v.to_string_compact(), "[null]");
+        assert_eq!(v.to_embedded_string(), r#""[null]""#);
+        assert_eq!(format!("{:?}", v), r#"["NaN"]"#);
+        assert_eq!(v.to_json5_string(&SerializeOptions::new()), "[NaN]");
+    }
+
+    #[test]
+    fn compact_output_has_no_spaces_outside_strings() {
+        let v: Value = r#"{ "a b": [1, 2.5, { "c": " d " }], "e": [ ], "f": null }"#.parse().unwrap();
+        let compact = v.to_string_compact();
+        let mut in_string = false;
+        let mut escaped = false;
+        for c in compact.chars() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' if in_string => escaped = true,
+                '"' => in_string = !in_string,
+                c if !in_string => assert!(!c.is_whitespace(), "whitespace in {}", compact),
+                _ => {},
+            }
+        }
+        assert_eq!(compact.parse::<Value>().unwrap(), v);
+    }
+
+    // Counts the write calls it receives, to see that output isn't buffered up first
+    struct CountingWriter {
+        writes: usize,
+        bytes: Vec<u8>,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn to_writer_streams() {
+        let v = Array((0..1000).map(Int).collect());
+        let mut writer = CountingWriter { writes: 0, bytes: Vec::new() };
+        v.to_writer(&mut writer).unwrap();
+        // At least one write per element, rather than a single one of the whole output
+        assert!(writer.writes > 1000, "{} writes", writer.writes);
+        assert_eq!(writer.bytes, v.to_bytes());
+    }
+
+    #[test]
+    fn ascii_only_escapes_accents_and_emoji() {
+        let options = SerializeOptions::new().ascii_only(true);
+        assert_eq!(JsonString("é😀".to_string()).to_string_with(&options), r#""\u00e9\ud83d\ude00""#);
+
+        let mut map = HashMap::new();
+        map.insert("clé".to_string(), JsonString("naïve café".to_string()));
+        let ascii = Object(map).to_string_with(&options);
+        assert_eq!(ascii, r#"{"cl\u00e9":"na\u00efve caf\u00e9"}"#);
+        assert!(ascii.is_ascii());
+
+        // Reads back to the same chars, and without the option nothing is escaped
+        let emoji = JsonString("😀".to_string());
+        assert_eq!(emoji.to_string_with(&options).parse::<Value>().unwrap(), emoji);
+        assert_eq!(emoji.to_string(), "\"😀\"");
+    }
+
+    #[test]
+    fn json5_accepts_a_leading_plus() {
+        let json5 = |input: &str| ParserBuilder::new().json5(true).build(input).try_parse().map_err(|e| e.root().clone());
+        assert_eq!(json5("+5").unwrap(), Int(5));
+        assert_eq!(json5("+3.14").unwrap().to_string(), "3.14");
+        assert_eq!(json5("[+1, -1]").unwrap(), Array(vec![Int(1), Int(-1)]));
+        assert_eq!(json5("+").unwrap_err(), ParseError::InvalidNumber("+".to_string()));
+        assert_eq!(json5("+-1").unwrap_err(), ParseError::InvalidNumber("+-1".to_string()));
+
+        for input in ["+5", "+3.14", "+"] {
+            assert_eq!(Parser::new(input).try_parse().unwrap_err().root(), &ParseError::UnexpectedChar('+'));
+        }
+    }
+
+    #[test]
+    fn from_slice_rejects_trailing_input() {
+        assert_eq!(from_slice(b" [1] ").unwrap(), Array(vec![Int(1)]));
+        assert_eq!(from_slice(b"1 \xff").unwrap_err(), ParseError::InvalidUtf8 { offset: 2 });
+        assert!(from_slice(b"[1] x").is_err());
+        assert!(from_slice(b"1 2").is_err());
+    }
+
+    #[test]
+    fn strict_mode_rejects_hex() {
+        let err = Parser::new("0xFF").try_parse().unwrap_err();
+        assert_eq!(err.root(), &ParseError::InvalidNumber("0xFF".to_string()));
+        assert_eq!(Parser::new("[12abc]").try_parse().unwrap_err().root(), &ParseError::InvalidNumber("12abc".to_string()));
+        assert_eq!(ParserBuilder::new().json5(true).build("0xFF").try_parse().unwrap(), Int(255));
+    }
+
+    // Integers have to fit into an i32, anything else is rejected
+    fn i32_only(literal: &str, _options: &ParserOptions) -> Result<Value, ParseError> {
+        literal.parse::<i32>()
+            .map(|i| Int(i64::from(i)))
+            .map_err(|_| ParseError::OutOfRange { target: "i32" })
+    }
+
+    #[test]
+    fn number_hook_can_reject() {
+        let parse = |input| ParserBuilder::new().number_hook(i32_only).parse(input);
+        assert_eq!(parse("[1, -2]").unwrap(), Array(vec![Int(1), Int(-2)]));
+        assert_eq!(parse("2147483648").unwrap_err().root(), &ParseError::OutOfRange { target: "i32" });
+        assert!(parse("0.5").is_err());
+    }
+
+    #[test]
+    fn f32_rejects_overflow() {
+        assert_eq!(f32::from_value(Float(1e300)), Err(ParseError::OutOfRange { target: "f32" }));
+        assert_eq!(f32::from_value(Float(-1e39)), Err(ParseError::OutOfRange { target: "f32" }));
+        assert_eq!(f32::from_value(Float(0.5)), Ok(0.5));
+        assert_eq!(f32::from_value(Float(f64::INFINITY)), Ok(f32::INFINITY));
+
+        let v = Array(vec![Float(1.0), Float(1e300)]);
+        assert!(!v.numbers_fit::<f32>());
+        assert!(v.numbers_fit::<f64>());
+    }
+
+    #[test]
+    fn fixed_arrays() {
+        let v: Value = "[1.5, 2, 3]".parse().unwrap();
+        assert_eq!(v.to_fixed_array::<f64, 3>(), Ok([1.5, 2.0, 3.0]));
+        assert_eq!(v.to_fixed_array::<f64, 2>(), Err(ParseError::LengthMismatch { expected: 2, found: 3 }));
+        assert_eq!(v.to_fixed_array::<i64, 3>(), Err(ParseError::TypeMismatch { expected: "integer", found: "float" }));
+        assert_eq!(<[i64; 2]>::from_value("[4, 5]".parse().unwrap()), Ok([4, 5]));
+        assert!(Null.to_fixed_array::<i64, 0>().is_err());
+    }
+
+    #[test]
+    fn lazy_array_parses_elements_on_demand() {
+        let input = r#" [1, "é,]", {"a": [2]}, {"b" 3}] "#;
+        let lazy = LazyArray::parse(input, ParserOptions::default()).unwrap();
+        assert_eq!(lazy.len(), 4);
+        assert_eq!(lazy.raw(1), Some(r#""é,]""#));
+        assert_eq!(lazy.get(2).unwrap().unwrap(), r#"{"a": [2]}"#.parse::<Value>().unwrap());
+        assert!(lazy.get(4).is_none());
+        // Errors inside an element are located within the whole input
+        match lazy.get(3).unwrap().unwrap_err() {
+            ParseError::Located { offset, .. } => assert_eq!(offset, 29),
+            e => panic!("{:?}", e),
+        }
+
+        assert!(LazyArray::parse("{}", ParserOptions::default()).is_err());
+        assert!(LazyArray::parse("[1] 2", ParserOptions::default()).is_err());
+        assert!(LazyArray::parse("[{]", ParserOptions::default()).is_err());
+        let trailing = ParserOptions { allow_trailing_commas: true, ..ParserOptions::default() };
+        assert_eq!(LazyArray::parse("[1,]", trailing).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn number_syntax() {
+        for valid in ["0", "-0", "12", "1.5", "-0.25", "1e5", "1E+5", "2.5e-3"] {
+            assert!(Parser::new(valid).try_parse().is_ok(), "{}", valid);
+        }
+        for invalid in ["01", "-01", "00", "1.", "1.e5", "1e", "1e+", "-"] {
+            let err = Parser::new(invalid).try_parse().unwrap_err();
+            assert_eq!(err.root(), &ParseError::InvalidNumber(invalid.to_string()));
+        }
+        assert_eq!(ParserBuilder::new().json5(true).build("5.").try_parse().unwrap().to_string(), "5.0");
+    }
+
+    // Keeps every literal as a string, whatever its size
+    fn decimal(literal: &str, _options: &ParserOptions) -> Result<Value, ParseError> {
+        Ok(JsonString(literal.to_string()))
+    }
+
+    #[test]
+    fn number_hook_sees_literals_before_conversion() {
+        let huge = format!("[1{}, 0.1, -2e500]", "0".repeat(400));
+        let v = ParserBuilder::new().number_hook(decimal).build(&huge).try_parse().unwrap();
+        assert_eq!(v[0], JsonString(format!("1{}", "0".repeat(400))));
+        assert_eq!(v[2], JsonString("-2e500".to_string()));
+        assert!(ParserBuilder::new().number_hook(decimal).build("[01]").try_parse().is_err());
+        // Lone digits too, even though they skip the literal otherwise
+        assert_eq!(ParserBuilder::new().number_hook(decimal).parse("[7]").unwrap(), Array(vec![JsonString("7".to_string())]));
+        assert_eq!(ParserBuilder::new().raw_numbers(true).parse("7").unwrap(), Number { value: 7.0, raw: "7".to_string() });
+
+        // The default hook honours the options, such as big_integers
+        let big = ParserBuilder::new().big_integers(true).number_hook(default_number_hook).build("18446744073709551615");
+        assert_eq!(big.try_parse().unwrap(), BigInt(18446744073709551615));
+        let plain = ParserBuilder::new().number_hook(default_number_hook).build("[1, 2.5]");
+        assert_eq!(plain.try_parse().unwrap(), Parser::new("[1, 2.5]").try_parse().unwrap());
+    }
+
+    #[test]
+    fn estimate_size_honours_parser_options() {
+        let expected = SizeEstimate { values: 4, objects: 1, arrays: 1, strings: 2, string_bytes: 4 };
+        assert_eq!(estimate_size(r#"{"ab": [1, "cd"]}"#), Ok(expected));
+
+        let jsonc = "{\"ab\": [1, \"cd\",], // trailing\n}";
+        assert!(estimate_size(jsonc).is_err());
+        let options = ParserBuilder::new().allow_comments(true).allow_trailing_commas(true);
+        assert_eq!(options.build(jsonc).estimate_size(), Ok(expected));
+
+        let deep = "[[[0]]]";
+        assert!(ParserBuilder::new().max_depth(3).build(deep).estimate_size().is_ok());
+        let too_deep = ParserBuilder::new().max_depth(2).build(deep).estimate_size().unwrap_err();
+        assert_eq!(too_deep.root(), &ParseError::MaxDepthExceeded(2));
+
+        let repeated = r#"{"a": 1, "a": 2}"#;
+        assert!(estimate_size(repeated).is_ok());
+        let strict = ParserBuilder::new().duplicate_keys(DuplicateKeys::Error).build(repeated);
+        assert!(strict.estimate_size().is_err());
+    }
+
+    fn push_bytewise(input: &str, options: ParserOptions) -> Result<Value, ParseError> {
+        let mut p = PushParser::with_options(options);
+        for b in input.as_bytes() {
+            p.feed(std::slice::from_ref(b));
+        }
+        p.finish()
+    }
+
+    #[test]
+    fn push_parser_tokens_across_chunks() {
+        let long = "x".repeat(100_000);
+        let input = format!(r#"["{}", "a\"b\\", -12.5e3, 1234567890123]"#, long);
+        let expected = Array(vec![JsonString(long), JsonString("a\"b\\".into()), Float(-12.5e3), Int(1234567890123)]);
+        assert_eq!(push_bytewise(&input, ParserOptions::default()), Ok(expected));
+
+        let json5 = ParserOptions { json5: true, ..Default::default() };
+        assert_eq!(push_bytewise(r#"{"a": 0x1F}"#, json5.clone()), push_bytewise(r#"{"a": 31}"#, ParserOptions::default()));
+        assert_eq!(push_bytewise("7", json5), Ok(Int(7)));
+        assert!(push_bytewise("[1x]", ParserOptions::default()).is_err());
+        assert!(push_bytewise(r#"["open"#, ParserOptions::default()).is_err());
+    }
+
+    #[test]
+    fn shared_clone_leaves_self_alone() {
+        let plain = Array(vec![Int(1)]);
+        assert_eq!(plain.shared_clone(), plain);
+        assert!(matches!(plain, Array(_)));
+
+        let shared = Value::shared(Array(vec![Int(1)]));
+        let copy = shared.shared_clone();
+        match (&shared, &copy) {
+            (Shared(a), Shared(b)) => assert!(Arc::ptr_eq(a, b)),
+            _ => panic!("expected both to be Shared"),
+        }
+    }
+
+    #[test]
+    fn content_hash_is_pinned() {
+        let hash = |input: &str| input.parse::<Value>().unwrap().content_hash();
+        // Changing these breaks every hash stored so far, content_hash promises not to
+        assert_eq!(hash("null"), 0xaf63_bd4c_8601_b7df);
+        assert_eq!(hash(r#"{"a": [1, 2.5, null]}"#), 0x41c2_dbca_202c_9e49);
+        assert_eq!(hash(r#"{"b": true, "a": "x"}"#), 0x77eb_d2de_cbc6_f671);
+        assert_eq!(hash(r#"{"a": "x", "b": true}"#), hash(r#"{"b": true, "a": "x"}"#));
+        assert_eq!(hash("-0.0"), hash("0.0"));
+    }
+
+    #[test]
+    fn shared_nodes_copy_on_write() {
+        let strong_count = |val: &Value| match val {
+            Shared(arc) => Arc::strong_count(arc),
+            _ => panic!("expected a Shared node"),
+        };
+
+        let original = Value::shared(Array(vec![Int(1)]));
+        let mut copy = original.clone();
+        assert_eq!(strong_count(&original), 2);
+        copy.push(Int(2)).unwrap();
+        assert_eq!(original, Array(vec![Int(1)]));
+        assert_eq!(copy, Array(vec![Int(1), Int(2)]));
+        assert_eq!(strong_count(&original), 1);
+
+        let original = Value::shared(Object(HashMap::new()));
+        let mut copy = original.clone();
+        copy.insert("a", Null).unwrap();
+        assert_eq!(original, Object(HashMap::new()));
+        assert_eq!(copy.get_map("a"), Some(&Null));
+        assert_eq!(strong_count(&original), 1);
+
+        // A shared subtree inside a plain tree
+        let mut tree = Array(vec![original.clone()]);
+        let before = strong_count(&original);
+        *tree.get_path_mut(&[PathSegment::Index(0)]).unwrap().resolve_mut() = Bool(true);
+        assert_eq!(original, Object(HashMap::new()));
+        assert_eq!(tree, Array(vec![Bool(true)]));
+        assert_eq!(strong_count(&original), before - 1);
+    }
+
+    #[test]
+    fn duplicate_keys_collect() {
+        let collect = |input: &str| {
+            let parsed = ParserBuilder::new().duplicate_keys(DuplicateKeys::Collect).build(input).try_parse().unwrap();
+            let mut push = PushParser::with_options(ParserOptions { duplicate_keys: DuplicateKeys::Collect, ..Default::default() });
+            push.feed(input.as_bytes());
+            assert_eq!(push.finish().unwrap(), parsed);
+            parsed.get_map("a").cloned().unwrap()
+        };
+
+        assert_eq!(collect(r#"{"a": 1, "a": 2}"#), Array(vec![Int(1), Int(2)]));
+        assert_eq!(collect(r#"{"a": 1, "b": 0, "a": 2, "a": 3}"#), Array(vec![Int(1), Int(2), Int(3)]));
+        assert_eq!(collect(r#"{"a": [1]}"#), Array(vec![Int(1)]));
+        assert_eq!(collect(r#"{"a": [1], "a": 2}"#), Array(vec![Array(vec![Int(1)]), Int(2)]));
+        assert_eq!(collect(r#"{"a": [1], "a": [2]}"#), Array(vec![Array(vec![Int(1)]), Array(vec![Int(2)])]));
+    }
+
+    #[test]
+    fn max_tokens_counts_every_token() {
+        // [ 1 , 2 ] is five tokens
+        assert_eq!(ParserBuilder::new().max_tokens(5).parse("[1, 2]").unwrap(), Array(vec![Int(1), Int(2)]));
+        let over = ParserBuilder::new().max_tokens(4).parse("[1, 2]").unwrap_err();
+        assert_eq!(over.root(), &ParseError::BudgetExceeded(4));
+    }
+
+    #[test]
+    fn comments_round_trip() {
+        let input = "// config\n{\n  // the name\n  \"name\": \"x\", // trailing\n  \"list\": [1, /* one */ 2]\n}";
+        let (val, comments) = Parser::new(input).parse_with_comments().unwrap();
+        let written = val.to_string_with_comments(&comments);
+        for comment in ["// config", "// the name", "// trailing", "/* one */"] {
+            assert!(written.contains(comment), "{} is missing from {}", comment, written);
+        }
+
+        let (reparsed, recomments) = Parser::new(&written).parse_with_comments().unwrap();
+        assert_eq!(reparsed, val);
+        assert_eq!(recomments, comments);
+    }
+
+    #[test]
+    fn element_types() {
+        let element_type = |input: &str| input.parse::<Value>().unwrap().element_type();
+        assert_eq!(element_type("[1, 2.5, 10000000000000000000]"), Some(Kind::Number));
+        assert_eq!(element_type(r#"["a", "b"]"#), Some(Kind::String));
+        assert_eq!(element_type("[[], [1]]"), Some(Kind::Array));
+        assert_eq!(element_type("[1, \"a\"]"), None);
+        assert_eq!(element_type("[]"), None);
+        assert!("[]".parse::<Value>().unwrap().is_homogeneous_array());
+        assert_eq!(Value::shared(Array(vec![Null])).element_type(), Some(Kind::Null));
+    }
+
+    #[cfg(feature = "arena")]
+    #[test]
+    fn arena_parser_honours_parser_options() {
+        let bump = Bump::new();
+        let input = r#"{"a": [1, "x\n", 2.5,], /* comment */ "a": 3}"#;
+        assert!(ArenaParser::new(input, &bump).try_parse().is_err());
+
+        let options = || ParserBuilder::new().allow_comments(true).allow_trailing_commas(true);
+        let val = options().build_arena(input, &bump).try_parse().unwrap();
+        assert_eq!(val.to_value(), options().parse(input).unwrap());
+        assert_eq!(val.get_map("a"), Some(&ArenaValue::Int(3)));
+
+        let first = options().duplicate_keys(DuplicateKeys::TakeFirst).build_arena(input, &bump).try_parse().unwrap();
+        assert_eq!(first.get_map("a").unwrap().get_arr(1), Some(&ArenaValue::JsonString("x\n")));
+        let collect = options().duplicate_keys(DuplicateKeys::Collect);
+        let collected = collect.clone().build_arena(input, &bump).try_parse().unwrap();
+        assert_eq!(collected.to_value(), collect.parse(input).unwrap());
+        let strict = options().duplicate_keys(DuplicateKeys::Error).build_arena(input, &bump).try_parse();
+        assert_eq!(strict.unwrap_err().root(), &ParseError::DuplicateKey("a".to_string()));
+
+        let deep = ParserBuilder::new().max_depth(1).build_arena("[[1]]", &bump).try_parse();
+        assert_eq!(deep.unwrap_err().root(), &ParseError::MaxDepthExceeded(1));
+        let raw = ParserBuilder::new().raw_numbers(true).build_arena("1.50", &bump).try_parse().unwrap();
+        assert_eq!(raw, &ArenaValue::Number { value: 1.5, raw: "1.50" });
+    }
+
+    #[test]
+    fn parse_validated_locates_the_first_mismatch() {
+        let decl = r#"{"id": "integer", "tags": ["string"], "name?": "string"}"#;
+        let schema = Schema::from_value(&decl.parse().unwrap()).unwrap();
+        let failure = |input| match Parser::new(input).parse_validated(&schema).unwrap_err() {
+            ParseError::Located { error, offset, .. } => match *error {
+                ParseError::AtPath { error, path } => (*error, path, offset),
+                e => panic!("{:?}", e),
+            },
+            e => panic!("{:?}", e),
+        };
+
+        let ok = r#"{"tags": ["a"], "id": 1}"#;
+        assert_eq!(Parser::new(ok).parse_validated(&schema).unwrap(), ok.parse::<Value>().unwrap());
+
+        let found = ParseError::TypeMismatch { expected: "string", found: "integer" };
+        assert_eq!(failure(r#"{"tags": ["a", 2], "id": 1}"#), (found, "/tags/1".to_string(), 15));
+        let found = ParseError::TypeMismatch { expected: "array", found: "object" };
+        assert_eq!(failure(r#"{"id": 1, "tags": {}}"#), (found, "/tags".to_string(), 18));
+
+        // Required fields are checked when the object closes, in key order
+        let missing = failure(r#"{"name": "x"}"#);
+        assert_eq!(missing, (ParseError::KeyNotFound("id".to_string()), "/id".to_string(), 0));
+        let nested = Schema::from_value(&r#"[{"b": "any", "a": "any"}]"#.parse().unwrap()).unwrap();
+        match Parser::new("[{}]").parse_validated(&nested).unwrap_err().root() {
+            ParseError::KeyNotFound(key) => assert_eq!(key, "a"),
+            e => panic!("{:?}", e),
+        }
+        match nested.validate(&"[{}]".parse().unwrap()).unwrap_err() {
+            ParseError::AtPath { path, .. } => assert_eq!(path, "/0/a"),
+            e => panic!("{:?}", e),
         }
     }
 }